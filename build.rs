@@ -0,0 +1,60 @@
+//! Embed build info for `--version`: git commit and the `windows` crate version
+
+use std::process::Command;
+
+fn main() {
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=WATCHDOG_GIT_COMMIT={}", commit);
+    for path in git_head_files() {
+        println!("cargo:rerun-if-changed={}", path);
+    }
+
+    // Resolved version from Cargo.lock (the manifest only holds the requirement)
+    let windows_version = std::fs::read_to_string("Cargo.lock")
+        .ok()
+        .and_then(|lock| {
+            let mut lines = lock.lines();
+            while let Some(line) = lines.next() {
+                if line == "name = \"windows\"" {
+                    return lines.next().and_then(|v| {
+                        v.strip_prefix("version = \"")
+                            .and_then(|v| v.strip_suffix('"'))
+                            .map(str::to_string)
+                    });
+                }
+            }
+            None
+        })
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=WATCHDOG_WINDOWS_CRATE={}", windows_version);
+    println!("cargo:rerun-if-changed=Cargo.lock");
+}
+
+/// Files that change when HEAD moves: HEAD itself (checkout), the branch ref it points to (a
+/// commit) and packed-refs (where the ref lives after `git gc`). Missing ones are left out, as
+/// cargo would rerun on every build for them.
+fn git_head_files() -> Vec<String> {
+    let git_path = |name: &str| {
+        Command::new("git")
+            .args(["rev-parse", "--git-path", name])
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+    };
+    let head = git_path("HEAD").unwrap_or_else(|| ".git/HEAD".to_string());
+    let branch = std::fs::read_to_string(&head)
+        .ok()
+        .and_then(|content| content.trim().strip_prefix("ref: ").and_then(&git_path));
+    [Some(head), branch, git_path("packed-refs")]
+        .into_iter()
+        .flatten()
+        .filter(|path| std::path::Path::new(path).exists())
+        .collect()
+}
@@ -1,4 +1,5 @@
 //! WLAN adapter enable: PowerShell (InterfaceType = Wireless80211) then netsh fallback.
+//! Also detects whether any Wi-Fi hardware exists at all (disabled vs. absent).
 
 use std::process::Command;
 
@@ -79,3 +80,75 @@ pub fn try_enable_wlan_adapter() -> bool {
     tracing::warn!("No WLAN interface could be enabled (PowerShell + netsh fallback)");
     false
 }
+
+/// Whether any Wi-Fi adapter exists, including disabled or hidden ones:
+/// Get-NetAdapter -IncludeHidden (InterfaceType = 71) plus Get-PnpDevice (Net class, wireless names).
+/// None if PowerShell could not be run or its output was unexpected.
+pub fn wifi_hardware_present() -> Option<bool> {
+    let ps = format!(
+        "@(Get-NetAdapter -IncludeHidden -ErrorAction SilentlyContinue | Where-Object {{ $_.InterfaceType -eq {} }}).Count + \
+         @(Get-PnpDevice -Class Net -ErrorAction SilentlyContinue | Where-Object {{ $_.FriendlyName -match 'Wi-?Fi|Wireless|WLAN|802\\.11' }}).Count",
+        INTERFACE_TYPE_WIRELESS_80211
+    );
+    let out = match Command::new("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command", &ps])
+        .output()
+    {
+        Ok(out) => out,
+        Err(e) => {
+            tracing::info!("Failed to run PowerShell: {}", e);
+            return None;
+        }
+    };
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    match stdout.trim().parse::<u32>() {
+        Ok(count) => {
+            tracing::info!("Wi-Fi hardware check: {} adapter/device match(es)", count);
+            Some(count > 0)
+        }
+        Err(_) => {
+            tracing::info!("Wi-Fi hardware check: unexpected output: {}", stdout.trim());
+            None
+        }
+    }
+}
+
+/// Run a PowerShell command for an escalation step; true if it exited successfully
+fn run_powershell(what: &str, ps: &str) -> bool {
+    tracing::info!("{} via PowerShell", what);
+    match Command::new("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command", ps])
+        .output()
+    {
+        Ok(out) if out.status.success() => true,
+        Ok(out) => {
+            tracing::info!(
+                "{} failed: {}",
+                what,
+                String::from_utf8_lossy(&out.stderr).trim()
+            );
+            false
+        }
+        Err(e) => {
+            tracing::info!("Failed to run PowerShell: {}", e);
+            false
+        }
+    }
+}
+
+/// `--escalate reset-adapter`: Restart-NetAdapter (disable + enable) on every Wi-Fi adapter
+pub fn restart_wlan_adapter() -> bool {
+    let ps = format!(
+        "Get-NetAdapter -ErrorAction Stop | Where-Object {{ $_.InterfaceType -eq {} }} | Restart-NetAdapter -Confirm:$false -ErrorAction Stop",
+        INTERFACE_TYPE_WIRELESS_80211
+    );
+    run_powershell("Restarting WLAN adapter", &ps)
+}
+
+/// `--escalate restart-wlansvc`: restart the WLAN AutoConfig service
+pub fn restart_wlan_service() -> bool {
+    run_powershell(
+        "Restarting WLAN AutoConfig (WlanSvc)",
+        "Restart-Service -Name WlanSvc -Force -ErrorAction Stop",
+    )
+}
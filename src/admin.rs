@@ -1,29 +1,127 @@
 //! Admin check and auto-elevation via gsudo (output stays in current terminal)
 
 use std::env;
+use std::os::windows::process::CommandExt;
 use std::process::Command;
 
 #[cfg(windows)]
 use windows::Win32::UI::Shell::IsUserAnAdmin;
 
+/// Whether this process runs with administrator rights
+pub fn is_admin() -> bool {
+    unsafe { IsUserAnAdmin().as_bool() }
+}
+
+/// Hidden argument appended to the gsudo relaunch so the child knows it should be elevated
+pub const ELEVATED_CHILD_ARG: &str = "--elevated-child";
+
+/// Environment marker set for the gsudo relaunch and inherited by anything the elevated
+/// instance starts (`--detach`, hooks re-running the watchdog): those never elevate again
+pub const ELEVATED_ENV: &str = "WATCHDOG_ELEVATED";
+
+/// Environment variables that may supply `--proxy-pass` / `--eap-pass` instead of the command
+/// line, e.g. for `--install-task`, which refuses to store passwords in the task definition
+pub const PROXY_PASS_ENV: &str = "WATCHDOG_PROXY_PASS";
+pub const EAP_PASS_ENV: &str = "WATCHDOG_EAP_PASS";
+
+/// Flags whose value is a secret, with the environment variable that can replace them
+pub const SECRET_FLAGS: &[(&str, &str)] = &[
+    ("--proxy-pass", PROXY_PASS_ENV),
+    ("--eap-pass", EAP_PASS_ENV),
+];
+
+/// `args` with the values of [`SECRET_FLAGS`] (`--flag VALUE` or `--flag=VALUE`) replaced by
+/// `***`, for logging
+pub fn mask_secrets(args: &[String]) -> Vec<String> {
+    let mut masked = Vec::with_capacity(args.len());
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match secret_flag(arg) {
+            Some((flag, _)) if arg.len() > flag.len() => masked.push(format!("{}=***", flag)),
+            Some(_) => {
+                masked.push(arg.clone());
+                if iter.next().is_some() {
+                    masked.push("***".to_string());
+                }
+            }
+            None => masked.push(arg.clone()),
+        }
+    }
+    masked
+}
+
+/// The [`SECRET_FLAGS`] entry `arg` spells, as `--flag` or `--flag=VALUE`
+pub fn secret_flag(arg: &str) -> Option<(&'static str, &'static str)> {
+    SECRET_FLAGS.iter().copied().find(|(flag, _)| {
+        arg.strip_prefix(flag)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('='))
+    })
+}
+
+/// Whether an earlier instance already went through elevation
+fn elevated_before() -> bool {
+    env::var_os(ELEVATED_ENV).is_some_and(|v| v == "1")
+}
+
+/// Error: this is the gsudo-launched instance but it still lacks administrator rights
+/// (UAC prompt declined, or gsudo fell back to running un-elevated)
+#[derive(Debug)]
+pub struct ElevationDenied;
+
+impl std::fmt::Display for ElevationDenied {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Elevation did not take effect: the relaunched instance is still not running as \
+             administrator (UAC prompt declined?). Accept the prompt, run from an elevated \
+             terminal, or use --best-effort-noadmin"
+        )
+    }
+}
+
+impl std::error::Error for ElevationDenied {}
+
 /// If not admin, re-launch this process as admin via gsudo (output in current terminal),
 /// wait for it to finish, then exit. If already admin, returns normally.
+/// In the relaunched instance (`elevated_child`, or `WATCHDOG_ELEVATED=1` inherited from an
+/// elevated parent) this never elevates again: still not admin fails with [`ElevationDenied`],
+/// so a restart loop cannot stack elevation prompts.
 ///
 /// Requires [gsudo](https://github.com/gerardog/gsudo) installed (e.g. `winget install gsudo`).
-pub fn ensure_admin_or_elevate() -> anyhow::Result<()> {
-    unsafe {
-        if IsUserAnAdmin().as_bool() {
-            return Ok(());
+pub fn ensure_admin_or_elevate(elevated_child: bool) -> anyhow::Result<()> {
+    if elevated_child || elevated_before() {
+        if !is_admin() {
+            return Err(ElevationDenied.into());
         }
+        // gsudo may not pass the variable through; set it for our own children
+        env::set_var(ELEVATED_ENV, "1");
+        return Ok(());
+    }
+    if is_admin() {
+        return Ok(());
     }
 
     let exe =
         env::current_exe().map_err(|e| anyhow::anyhow!("Failed to get current exe path: {}", e))?;
-    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<String> = env::args()
+        .skip(1)
+        .chain(std::iter::once(ELEVATED_CHILD_ARG.to_string()))
+        .collect();
+    let command_line = elevated_command_line(&exe.to_string_lossy(), &args);
 
     tracing::info!("Admin required, elevating via gsudo (output in current terminal)...");
+    tracing::debug!(
+        "Elevated command line: {}",
+        elevated_command_line(&exe.to_string_lossy(), &mask_secrets(&args))
+    );
 
-    let status = Command::new("gsudo").arg(&exe).args(&args).status();
+    // Pass our own quoted command line verbatim after `--` so gsudo doesn't re-split arguments
+    // containing spaces or quotes
+    let status = Command::new("gsudo")
+        .env(ELEVATED_ENV, "1")
+        .arg("--")
+        .raw_arg(&command_line)
+        .status();
 
     match status {
             Ok(s) => std::process::exit(s.code().unwrap_or(1)),
@@ -33,3 +131,248 @@ pub fn ensure_admin_or_elevate() -> anyhow::Result<()> {
             ),
     }
 }
+
+/// Quote one argument so CommandLineToArgvW / the MSVC runtime parse it back unchanged
+pub fn quote_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '\n', '\x0b', '"']) {
+        return arg.to_string();
+    }
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                // Escape the preceding backslashes and the quote itself
+                quoted.extend(std::iter::repeat_n('\\', backslashes * 2 + 1));
+                quoted.push('"');
+                backslashes = 0;
+            }
+            _ => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes));
+                quoted.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+    // Backslashes before the closing quote must be doubled
+    quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+/// Command line that re-runs `exe` with exactly `args`, each quoted for CommandLineToArgvW
+fn elevated_command_line(exe: &str, args: &[String]) -> String {
+    std::iter::once(exe)
+        .chain(args.iter().map(String::as_str))
+        .map(quote_arg)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task;
+
+    /// Split a command line the way CommandLineToArgvW does for arguments after the first:
+    /// 2n backslashes + quote -> n backslashes and a quote toggle, 2n+1 backslashes + quote ->
+    /// n backslashes and a literal quote, other backslashes are literal
+    fn split_command_line(line: &str) -> Vec<String> {
+        let mut args = Vec::new();
+        let mut chars = line.chars().peekable();
+        loop {
+            while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+            if chars.peek().is_none() {
+                return args;
+            }
+            let mut arg = String::new();
+            let mut in_quotes = false;
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => {
+                        let mut backslashes = 1;
+                        while chars.next_if_eq(&'\\').is_some() {
+                            backslashes += 1;
+                        }
+                        if chars.peek() == Some(&'"') {
+                            arg.extend(std::iter::repeat_n('\\', backslashes / 2));
+                            if backslashes % 2 == 1 {
+                                arg.push('"');
+                                chars.next();
+                            }
+                        } else {
+                            arg.extend(std::iter::repeat_n('\\', backslashes));
+                        }
+                    }
+                    '"' => in_quotes = !in_quotes,
+                    ' ' | '\t' if !in_quotes => break,
+                    _ => arg.push(c),
+                }
+            }
+            args.push(arg);
+        }
+    }
+
+    #[test]
+    fn plain_arg_is_unquoted() {
+        assert_eq!(quote_arg("--once"), "--once");
+    }
+
+    #[test]
+    fn quotes_arg_with_spaces() {
+        assert_eq!(quote_arg("Home Wi-Fi"), r#""Home Wi-Fi""#);
+    }
+
+    #[test]
+    fn escapes_embedded_quote() {
+        assert_eq!(quote_arg(r#"a"b"#), r#""a\"b""#);
+    }
+
+    #[test]
+    fn trailing_backslash_without_quoting_is_literal() {
+        assert_eq!(quote_arg(r"C:\dir\"), r"C:\dir\");
+    }
+
+    #[test]
+    fn doubles_trailing_backslash_before_closing_quote() {
+        assert_eq!(quote_arg(r"C:\my dir\"), r#""C:\my dir\\""#);
+    }
+
+    #[test]
+    fn empty_arg_is_an_empty_quoted_string() {
+        assert_eq!(quote_arg(""), r#""""#);
+    }
+
+    #[test]
+    fn forwarded_command_line_round_trips() {
+        let args: Vec<String> = [
+            "--profiles",
+            "Home Wi-Fi",
+            r#"a"b"#,
+            r"C:\dir\",
+            r"C:\my dir\",
+            r#"x\"y"#,
+            "",
+            "--once",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+        let line = elevated_command_line(r"C:\Program Files\nw\network-watchdog.exe", &args);
+        let parsed = split_command_line(&line);
+        assert_eq!(parsed[0], r"C:\Program Files\nw\network-watchdog.exe");
+        assert_eq!(parsed[1..], args[..]);
+    }
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn masks_secret_flag_values_in_both_spellings() {
+        let args = strings(&[
+            "--proxy-user",
+            "bob",
+            "--proxy-pass",
+            "hunter2",
+            "--eap-pass=s3cret",
+            "--proxy-passive",
+            "--interval",
+            "30",
+        ]);
+        assert_eq!(
+            mask_secrets(&args),
+            strings(&[
+                "--proxy-user",
+                "bob",
+                "--proxy-pass",
+                "***",
+                "--eap-pass=***",
+                "--proxy-passive",
+                "--interval",
+                "30",
+            ])
+        );
+        // A trailing flag without a value has nothing to mask
+        assert_eq!(
+            mask_secrets(&strings(&["--eap-pass"])),
+            strings(&["--eap-pass"])
+        );
+    }
+
+    #[test]
+    fn secret_flags_name_their_environment_variable() {
+        assert_eq!(
+            secret_flag("--proxy-pass"),
+            Some(("--proxy-pass", PROXY_PASS_ENV))
+        );
+        assert_eq!(
+            secret_flag("--eap-pass=x"),
+            Some(("--eap-pass", EAP_PASS_ENV))
+        );
+        assert_eq!(secret_flag("--eap-user"), None);
+    }
+
+    #[test]
+    fn task_forwarding_drops_task_management_flags() {
+        let args = strings(&[
+            "--install-task",
+            "--task-trigger",
+            "boot",
+            "--interval",
+            "30",
+            "--task-trigger=logon",
+            "--elevated-child",
+            "--profiles",
+            "Home",
+        ]);
+        assert_eq!(
+            task::forwarded_args(args),
+            strings(&["--interval", "30", "--profiles", "Home"])
+        );
+    }
+
+    #[test]
+    fn task_arguments_round_trip() {
+        let args = strings(&[
+            "--install-task",
+            "--profiles",
+            "Home Wi-Fi",
+            r#"a"b"#,
+            "--state-file",
+            r"C:\my dir\",
+        ]);
+        let forwarded = task::forwarded_args(args);
+        let line = forwarded
+            .iter()
+            .map(|a| quote_arg(a))
+            .collect::<Vec<_>>()
+            .join(" ");
+        assert_eq!(split_command_line(&line), forwarded);
+    }
+
+    #[test]
+    fn xml_escape_covers_element_text() {
+        assert_eq!(task::xml_escape("R&D <lab>"), "R&amp;D &lt;lab&gt;");
+        assert_eq!(task::xml_escape(r#"say "hi""#), r#"say "hi""#);
+    }
+
+    #[test]
+    fn task_xml_carries_escaped_command_arguments_and_working_dir() {
+        let xml = task::task_xml(
+            task::TaskTrigger::Boot,
+            r"C:\Tools & Co\network-watchdog.exe",
+            r#"--profiles "R&D <lab>""#,
+            r"C:\Users\me\watchdog",
+        );
+        assert!(xml.contains("<BootTrigger>"));
+        assert!(xml.contains("<UserId>S-1-5-18</UserId>"));
+        assert!(xml.contains(r"<Command>C:\Tools &amp; Co\network-watchdog.exe</Command>"));
+        assert!(xml.contains(r#"<Arguments>--profiles "R&amp;D &lt;lab&gt;"</Arguments>"#));
+        assert!(xml.contains(r"<WorkingDirectory>C:\Users\me\watchdog</WorkingDirectory>"));
+        let logon = task::task_xml(task::TaskTrigger::Logon, "nw.exe", "", r"C:\");
+        assert!(logon.contains("<LogonTrigger>"));
+        assert!(logon.contains("<LogonType>InteractiveToken</LogonType>"));
+    }
+}
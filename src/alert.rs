@@ -0,0 +1,11 @@
+//! Audible alert when a recovery round fails (`--beep-on-fail`)
+
+use windows::Win32::System::Diagnostics::Debug::MessageBeep;
+use windows::Win32::UI::WindowsAndMessaging::MB_ICONHAND;
+
+/// Play the system "critical stop" sound
+pub fn beep_failure() {
+    if let Err(e) = unsafe { MessageBeep(MB_ICONHAND) } {
+        tracing::debug!("MessageBeep failed: {}", e);
+    }
+}
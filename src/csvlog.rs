@@ -0,0 +1,233 @@
+//! `--csv-log`: one row per probe (time, result, latency, SSID, signal, IP) for spreadsheets,
+//! with optional rotation by day or size (`--csv-rotate`)
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+
+use crate::wlan::ConnectedNetwork;
+
+const HEADER: &str = "timestamp,state,latency_ms,ssid,signal,ip\r\n";
+
+/// When `--csv-log` starts a new file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CsvRotate {
+    /// When the local date changes
+    Daily,
+    /// When the file reaches this many bytes
+    Size(u64),
+}
+
+impl FromStr for CsvRotate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.trim().to_ascii_lowercase();
+        if lower == "daily" {
+            return Ok(Self::Daily);
+        }
+        let (digits, unit) = match lower.find(|c: char| !c.is_ascii_digit()) {
+            Some(i) => lower.split_at(i),
+            None => (lower.as_str(), ""),
+        };
+        let multiplier = match unit {
+            "" | "b" => 1,
+            "k" | "kb" => 1 << 10,
+            "m" | "mb" => 1 << 20,
+            "g" | "gb" => 1 << 30,
+            _ => return Err(format!("expected daily or a size like 10MB, got \"{}\"", s)),
+        };
+        match digits.parse::<u64>() {
+            Ok(n) if n > 0 => Ok(Self::Size(n.saturating_mul(multiplier))),
+            _ => Err(format!("expected daily or a size like 10MB, got \"{}\"", s)),
+        }
+    }
+}
+
+/// Appender for the `--csv-log` file
+pub struct CsvLog {
+    path: PathBuf,
+    rotate: Option<CsvRotate>,
+}
+
+impl CsvLog {
+    pub fn new(path: PathBuf, rotate: Option<CsvRotate>) -> Self {
+        Self { path, rotate }
+    }
+
+    /// Append one row. Each row is a single write to a file opened in append mode, so rows
+    /// written before a crash are on disk. Failures are logged, never fatal.
+    pub fn record(&self, up: bool, latency: Option<Duration>, conn: Option<&ConnectedNetwork>) {
+        if let Err(e) = self.append(up, latency, conn) {
+            tracing::warn!("Failed to write CSV log {}: {}", self.path.display(), e);
+        }
+    }
+
+    fn append(
+        &self,
+        up: bool,
+        latency: Option<Duration>,
+        conn: Option<&ConnectedNetwork>,
+    ) -> std::io::Result<()> {
+        let now = Local::now();
+        self.rotate_if_due(now);
+        if let Some(dir) = self.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let mut text = String::new();
+        if file.metadata()?.len() == 0 {
+            text.push_str(HEADER);
+        }
+        text.push_str(&row(now, up, latency, conn));
+        file.write_all(text.as_bytes())?;
+        file.flush()
+    }
+
+    /// Move the current file aside (`name-<date or time>.csv`) when `--csv-rotate` says so
+    fn rotate_if_due(&self, now: DateTime<Local>) {
+        let Some(rotate) = self.rotate else {
+            return;
+        };
+        let Ok(meta) = std::fs::metadata(&self.path) else {
+            return;
+        };
+        let suffix = match rotate {
+            CsvRotate::Daily => {
+                let Ok(modified) = meta.modified() else {
+                    return;
+                };
+                let day = DateTime::<Local>::from(modified).date_naive();
+                if day == now.date_naive() {
+                    return;
+                }
+                day.format("%Y-%m-%d").to_string()
+            }
+            CsvRotate::Size(limit) => {
+                if meta.len() < limit {
+                    return;
+                }
+                now.format("%Y-%m-%d-%H%M%S").to_string()
+            }
+        };
+        let target = rotated_path(&self.path, &suffix);
+        match std::fs::rename(&self.path, &target) {
+            Ok(()) => tracing::info!("CSV log rotated to {}", target.display()),
+            Err(e) => tracing::warn!(
+                "Failed to rotate CSV log {} to {}: {}",
+                self.path.display(),
+                target.display(),
+                e
+            ),
+        }
+    }
+}
+
+/// `dir/name.csv` -> `dir/name-<suffix>.csv`
+fn rotated_path(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, suffix, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, suffix),
+    };
+    path.with_file_name(name)
+}
+
+/// One CSV line for a probe at `now`
+fn row(
+    now: DateTime<Local>,
+    up: bool,
+    latency: Option<Duration>,
+    conn: Option<&ConnectedNetwork>,
+) -> String {
+    // Ethernet connections (`--ethernet-aware`) have no SSID or signal
+    let wifi = conn.filter(|c| !c.ssid.is_empty());
+    // Prefer an IPv4 address; IPv6 lists usually start with the link-local one
+    let ip = conn
+        .and_then(|c| c.ip.as_ref())
+        .and_then(|ip| {
+            ip.addresses
+                .iter()
+                .find(|a| !a.contains(':'))
+                .or(ip.addresses.first())
+        })
+        .cloned()
+        .unwrap_or_default();
+    format!(
+        "{},{},{},{},{},{}\r\n",
+        now.format("%Y-%m-%dT%H:%M:%S%:z"),
+        if up { "up" } else { "down" },
+        latency.map_or(String::new(), |d| d.as_millis().to_string()),
+        field(wifi.map_or("", |c| c.ssid.as_str())),
+        wifi.map_or(String::new(), |c| c.signal_quality.to_string()),
+        field(&ip)
+    )
+}
+
+/// Quote a field containing a separator, quote or line break (RFC 4180)
+fn field(s: &str) -> String {
+    if s.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotate_parses_daily_and_sizes() {
+        assert_eq!("daily".parse(), Ok(CsvRotate::Daily));
+        assert_eq!(" Daily ".parse(), Ok(CsvRotate::Daily));
+        assert_eq!("512".parse(), Ok(CsvRotate::Size(512)));
+        assert_eq!("100b".parse(), Ok(CsvRotate::Size(100)));
+        assert_eq!("512KB".parse(), Ok(CsvRotate::Size(512 << 10)));
+        assert_eq!("10mb".parse(), Ok(CsvRotate::Size(10 << 20)));
+        assert_eq!("2G".parse(), Ok(CsvRotate::Size(2 << 30)));
+    }
+
+    #[test]
+    fn rotate_rejects_zero_and_unknown_units() {
+        for bad in ["", "0", "0MB", "MB", "10TB", "weekly", "1.5MB", "-1"] {
+            assert!(bad.parse::<CsvRotate>().is_err(), "{:?} parsed", bad);
+        }
+    }
+
+    #[test]
+    fn field_quotes_only_when_needed() {
+        assert_eq!(field("Home"), "Home");
+        assert_eq!(field(""), "");
+        assert_eq!(field("a,b"), "\"a,b\"");
+        assert_eq!(field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(field("two\r\nlines"), "\"two\r\nlines\"");
+        assert_eq!(field("line\n"), "\"line\n\"");
+    }
+
+    #[test]
+    fn rotated_path_inserts_suffix_before_extension() {
+        assert_eq!(
+            rotated_path(Path::new("logs/probes.csv"), "2024-05-01"),
+            PathBuf::from("logs/probes-2024-05-01.csv")
+        );
+        assert_eq!(
+            rotated_path(Path::new("probes"), "2024-05-01-120000"),
+            PathBuf::from("probes-2024-05-01-120000")
+        );
+        assert_eq!(
+            rotated_path(Path::new("my.probes.csv"), "x"),
+            PathBuf::from("my.probes-x.csv")
+        );
+    }
+}
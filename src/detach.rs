@@ -0,0 +1,77 @@
+//! `--detach`: re-launch the watchdog in the background without a console, in its own job
+//! object, logging to a file
+
+use std::env;
+use std::ffi::OsString;
+use std::os::windows::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::CloseHandle;
+use windows::Win32::System::JobObjects::{AssignProcessToJobObject, CreateJobObjectW};
+use windows::Win32::System::Threading::{
+    OpenProcess, CREATE_BREAKAWAY_FROM_JOB, CREATE_NEW_PROCESS_GROUP, DETACHED_PROCESS,
+    PROCESS_SET_QUOTA, PROCESS_TERMINATE,
+};
+
+/// Log file of a detached instance without `--log-file`:
+/// %ProgramData%\network-watchdog\watchdog.log, next to the state file
+pub fn default_log_path() -> PathBuf {
+    crate::state::default_path().with_file_name("watchdog.log")
+}
+
+/// Start this program again with the same arguments minus `--detach` (plus `--log-file
+/// <log_file>` when given), as a detached process in its own process group and job object: no
+/// console window, unaffected by Ctrl+C in this terminal and, where the terminal's job allows
+/// breakaway, not ended when the terminal closes. Returns the child's PID; the caller exits
+/// right after.
+pub fn spawn_detached(log_file: Option<&Path>) -> anyhow::Result<u32> {
+    let exe =
+        env::current_exe().map_err(|e| anyhow::anyhow!("Failed to get current exe path: {}", e))?;
+    let mut args: Vec<OsString> = env::args_os()
+        .skip(1)
+        .filter(|a| a.as_os_str() != "--detach")
+        .collect();
+    if let Some(path) = log_file {
+        args.push("--log-file".into());
+        args.push(path.into());
+    }
+    let spawn = |flags: u32| {
+        Command::new(&exe)
+            .args(&args)
+            .creation_flags(flags)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+    };
+    let flags = DETACHED_PROCESS.0 | CREATE_NEW_PROCESS_GROUP.0;
+    // Breakaway fails when the terminal's job forbids it; the child then stays in that job
+    let child = spawn(flags | CREATE_BREAKAWAY_FROM_JOB.0)
+        .or_else(|_| spawn(flags))
+        .map_err(|e| anyhow::anyhow!("Failed to start detached process: {}", e))?;
+    if let Err(e) = assign_own_job(child.id()) {
+        tracing::warn!(
+            "Failed to give the detached process its own job object: {}",
+            e
+        );
+    }
+    Ok(child.id())
+}
+
+/// Put the process in a new job object, which also collects the processes it starts (hooks and
+/// netsh). The job has no kill-on-close limit, so closing our handle leaves it running.
+fn assign_own_job(pid: u32) -> anyhow::Result<()> {
+    unsafe {
+        let job = CreateJobObjectW(None, PCWSTR::null())?;
+        let assigned =
+            OpenProcess(PROCESS_SET_QUOTA | PROCESS_TERMINATE, false, pid).and_then(|process| {
+                let result = AssignProcessToJobObject(job, process);
+                let _ = CloseHandle(process);
+                result
+            });
+        let _ = CloseHandle(job);
+        Ok(assigned?)
+    }
+}
@@ -0,0 +1,74 @@
+//! 802.1X (enterprise) profiles: detection from profile XML and EAP user credentials
+
+use std::fmt;
+
+/// EAP method type for PEAP (RFC / IANA EAP type 25)
+const EAP_TYPE_PEAP: u32 = 25;
+
+/// EAP method type for MSCHAPv2, the usual PEAP inner method
+const EAP_TYPE_MSCHAPV2: u32 = 26;
+
+/// User credentials for 802.1X profiles (`--eap-user` / `--eap-pass`)
+#[derive(Clone)]
+pub struct EapCredentials {
+    /// User name, optionally `DOMAIN\user`
+    pub user: String,
+    pub pass: String,
+}
+
+impl fmt::Debug for EapCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EapCredentials")
+            .field("user", &self.user)
+            .field("pass", &"***")
+            .finish()
+    }
+}
+
+/// Whether the profile XML describes an 802.1X (enterprise) network
+pub fn is_802_1x(profile_xml: &str) -> bool {
+    profile_xml.contains("<OneX")
+}
+
+/// Whether the profile's outer EAP method is PEAP (the only one we build user data for)
+pub fn is_peap(profile_xml: &str) -> bool {
+    profile_xml.contains(&format!("Type>{}</", EAP_TYPE_PEAP))
+}
+
+/// Whether the profile authenticates with machine credentials only (no user credentials needed)
+pub fn is_machine_auth(profile_xml: &str) -> bool {
+    profile_xml.contains("<authMode>machine</authMode>")
+}
+
+/// Escape text for use inside an XML element
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// EapHostUserCredentials XML for PEAP-MSCHAPv2, as taken by WlanSetProfileEapXmlUserData
+pub fn peap_mschapv2_user_data(creds: &EapCredentials) -> String {
+    let (domain, user) = creds
+        .user
+        .split_once('\\')
+        .unwrap_or(("", creds.user.as_str()));
+    let domain = xml_escape(domain);
+    let user = xml_escape(user);
+    let pass = xml_escape(&creds.pass);
+    format!(
+        r#"<?xml version="1.0"?>
+<EapHostUserCredentials xmlns="http://www.microsoft.com/provisioning/EapHostUserCredentials" xmlns:eapCommon="http://www.microsoft.com/provisioning/EapCommon" xmlns:baseEap="http://www.microsoft.com/provisioning/BaseEapMethodUserCredentials">
+<EapMethod><eapCommon:Type>{peap}</eapCommon:Type><eapCommon:AuthorId>0</eapCommon:AuthorId></EapMethod>
+<Credentials xmlns:eapUser="http://www.microsoft.com/provisioning/EapUserPropertiesV1" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xmlns:baseEap="http://www.microsoft.com/provisioning/BaseEapUserPropertiesV1" xmlns:MsPeap="http://www.microsoft.com/provisioning/MsPeapUserPropertiesV1" xmlns:MsChapV2="http://www.microsoft.com/provisioning/MsChapV2UserPropertiesV1">
+<baseEap:Eap><baseEap:Type>{peap}</baseEap:Type><MsPeap:EapType><MsPeap:RoutingIdentity>{user}</MsPeap:RoutingIdentity>
+<baseEap:Eap><baseEap:Type>{mschapv2}</baseEap:Type><MsChapV2:EapType><MsChapV2:Username>{user}</MsChapV2:Username><MsChapV2:Password>{pass}</MsChapV2:Password><MsChapV2:LogonDomain>{domain}</MsChapV2:LogonDomain></MsChapV2:EapType></baseEap:Eap>
+</MsPeap:EapType></baseEap:Eap>
+</Credentials>
+</EapHostUserCredentials>"#,
+        peap = EAP_TYPE_PEAP,
+        mschapv2 = EAP_TYPE_MSCHAPV2,
+    )
+}
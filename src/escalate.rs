@@ -0,0 +1,148 @@
+//! Recovery escalation ladder (`--escalate`): lighter remedies first, each followed by a
+//! re-probe, before the usual radio-on + saved-profile round
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::network::Prober;
+use crate::wlan::{self, ConnectedNetwork, RecoveryOptions, RecoveryReport};
+use crate::{adapter, radio};
+
+/// One rung of the ladder
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Rung {
+    /// Disconnect and reconnect the current profile
+    Reconnect,
+    /// Turn the Wi-Fi radio off and on again
+    RadioCycle,
+    /// Restart the Wi-Fi network adapter (disable + enable)
+    ResetAdapter,
+    /// Restart the WLAN AutoConfig service (WlanSvc)
+    RestartWlansvc,
+}
+
+impl Rung {
+    fn name(self) -> &'static str {
+        match self {
+            Rung::Reconnect => "reconnect",
+            Rung::RadioCycle => "radio-cycle",
+            Rung::ResetAdapter => "reset-adapter",
+            Rung::RestartWlansvc => "restart-wlansvc",
+        }
+    }
+
+    fn needs_admin(self) -> bool {
+        !matches!(self, Rung::Reconnect)
+    }
+}
+
+/// How long a rung gets for Windows to reconnect on its own before the next one is tried
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Re-probe interval while waiting for a rung to take effect
+const SETTLE_POLL: Duration = Duration::from_secs(3);
+
+/// Walk the ladder in order; returns the connection restored by the first rung that worked,
+/// None if every rung failed (or was skipped) and the regular round should run
+pub async fn climb(
+    ladder: &[Rung],
+    prober: &Arc<Prober>,
+    opts: &RecoveryOptions,
+    report: &mut RecoveryReport,
+) -> Option<ConnectedNetwork> {
+    for (idx, &rung) in ladder.iter().enumerate() {
+        if opts.shutdown.is_cancelled() {
+            return None;
+        }
+        if rung.needs_admin() && opts.skip_admin_steps {
+            tracing::info!(
+                "Escalation {}/{} ({}): needs administrator rights (--best-effort-noadmin), skipped",
+                idx + 1,
+                ladder.len(),
+                rung.name()
+            );
+            continue;
+        }
+        tracing::info!("Escalation {}/{}: {}", idx + 1, ladder.len(), rung.name());
+        let restored = match rung {
+            Rung::Reconnect => {
+                let p = Arc::clone(prober);
+                let test_network = move || -> wlan::NetworkTestFuture {
+                    let p = Arc::clone(&p);
+                    Box::pin(async move { p.probe().await })
+                };
+                match wlan::reconnect_connected(test_network, opts, report).await {
+                    Ok(restored) => restored,
+                    Err(e) => {
+                        tracing::info!("Reconnect failed: {}", e);
+                        None
+                    }
+                }
+            }
+            Rung::RadioCycle => {
+                let done = match radio::cycle_wifi_radio().await {
+                    Ok(()) => true,
+                    Err(e) => {
+                        tracing::info!("Radio cycle failed: {}", e);
+                        false
+                    }
+                };
+                settled(rung, done, prober, opts).await
+            }
+            Rung::ResetAdapter => {
+                settled(rung, adapter::restart_wlan_adapter(), prober, opts).await
+            }
+            Rung::RestartWlansvc => {
+                settled(rung, adapter::restart_wlan_service(), prober, opts).await
+            }
+        };
+        if let Some(conn) = restored {
+            tracing::info!(
+                "Network restored by escalation rung {}/{} ({})",
+                idx + 1,
+                ladder.len(),
+                rung.name()
+            );
+            return Some(conn);
+        }
+        tracing::info!("{} did not restore the network, escalating", rung.name());
+    }
+    None
+}
+
+/// After a rung that resets the link without picking a profile (`done`: it ran), wait for
+/// Windows to reconnect on its own; the connection it came back on, or None
+async fn settled(
+    rung: Rung,
+    done: bool,
+    prober: &Prober,
+    opts: &RecoveryOptions,
+) -> Option<ConnectedNetwork> {
+    if !(done && settle(prober, opts).await) {
+        return None;
+    }
+    Some(
+        wlan::current_connection().unwrap_or_else(|| ConnectedNetwork {
+            profile: rung.name().to_string(),
+            ssid: String::new(),
+            bssid: [0; 6],
+            signal_quality: 0,
+            ip: None,
+        }),
+    )
+}
+
+/// Re-probe until the network is back or `SETTLE_TIMEOUT` passes
+async fn settle(prober: &Prober, opts: &RecoveryOptions) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < SETTLE_TIMEOUT {
+        tokio::select! {
+            _ = tokio::time::sleep(SETTLE_POLL) => {}
+            _ = opts.shutdown.cancelled() => return false,
+        }
+        if prober.probe().await {
+            return true;
+        }
+    }
+    false
+}
@@ -0,0 +1,43 @@
+//! User hook commands run on network state changes (`--on-down`, `--on-restored`)
+
+use std::time::Duration;
+
+use tokio::process::Command;
+
+/// Default hook timeout in seconds
+pub const DEFAULT_HOOK_TIMEOUT_SECS: u64 = 30;
+
+/// Run `cmd` through `cmd /C`, passing the connection context as environment variables
+/// (WATCHDOG_EVENT, WATCHDOG_SSID, WATCHDOG_PROFILE; empty when unknown).
+/// The hook is killed if it runs longer than `timeout_secs`; failures are logged, never fatal.
+pub async fn run_hook(
+    event: &str,
+    cmd: &str,
+    ssid: Option<&str>,
+    profile: Option<&str>,
+    timeout_secs: u64,
+) {
+    tracing::info!("Running {} hook: {}", event, cmd);
+    let child = Command::new("cmd")
+        .args(["/C", cmd])
+        .env("WATCHDOG_EVENT", event)
+        .env("WATCHDOG_SSID", ssid.unwrap_or_default())
+        .env("WATCHDOG_PROFILE", profile.unwrap_or_default())
+        .kill_on_drop(true)
+        .status();
+
+    match tokio::time::timeout(Duration::from_secs(timeout_secs), child).await {
+        Ok(Ok(status)) if status.success() => {
+            tracing::info!("{} hook finished", event);
+        }
+        Ok(Ok(status)) => {
+            tracing::warn!("{} hook exited with {}", event, status);
+        }
+        Ok(Err(e)) => {
+            tracing::warn!("Failed to run {} hook: {}", event, e);
+        }
+        Err(_) => {
+            tracing::warn!("{} hook timed out after {} s, killed", event, timeout_secs);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Global "check now" hotkey (`--check-hotkey`)
+
+use std::str::FromStr;
+
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    RegisterHotKey, HOT_KEY_MODIFIERS, MOD_ALT, MOD_CONTROL, MOD_NOREPEAT, MOD_SHIFT, MOD_WIN,
+};
+use windows::Win32::UI::WindowsAndMessaging::{GetMessageW, MSG, WM_HOTKEY};
+
+/// Modifier names accepted in a hotkey, case-insensitive
+const MODIFIER_NAMES: &[(&str, HOT_KEY_MODIFIERS)] = &[
+    ("ctrl", MOD_CONTROL),
+    ("alt", MOD_ALT),
+    ("shift", MOD_SHIFT),
+    ("win", MOD_WIN),
+];
+
+/// A key combination such as "Ctrl+Alt+W": modifiers plus one letter, digit or F1-F24
+#[derive(Clone, Debug)]
+pub struct Hotkey {
+    text: String,
+    modifiers: HOT_KEY_MODIFIERS,
+    vk: u32,
+}
+
+impl FromStr for Hotkey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        let key = parts.pop().unwrap_or_default().to_ascii_uppercase();
+        let mut modifiers = MOD_NOREPEAT;
+        for name in parts {
+            let Some(&(_, value)) = MODIFIER_NAMES
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            else {
+                return Err(format!(
+                    "unknown modifier \"{}\" (supported: ctrl, alt, shift, win)",
+                    name
+                ));
+            };
+            modifiers |= value;
+        }
+        if modifiers == MOD_NOREPEAT {
+            return Err(format!(
+                "\"{}\" needs at least one modifier, e.g. Ctrl+Alt+W",
+                s
+            ));
+        }
+        // Virtual-key codes: letters and digits are their ASCII codes, F1 is 0x70
+        let vk = match key.as_bytes() {
+            [c] if c.is_ascii_alphanumeric() => *c as u32,
+            [b'F', n @ ..] => match std::str::from_utf8(n).ok().and_then(|n| n.parse().ok()) {
+                Some(n @ 1..=24u32) => 0x70 + n - 1,
+                _ => return Err(format!("unknown key \"{}\"", key)),
+            },
+            _ => {
+                return Err(format!(
+                    "unknown key \"{}\" (use a letter, digit or F1-F24)",
+                    key
+                ))
+            }
+        };
+        Ok(Self {
+            text: s.to_string(),
+            modifiers,
+            vk,
+        })
+    }
+}
+
+impl std::fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Register `hotkey` system-wide and call `on_press` each time it is pressed. Registration
+/// happens on a dedicated thread (the hotkey belongs to its message queue); fails if another
+/// program already owns the combination.
+pub fn spawn(hotkey: Hotkey, on_press: impl Fn() + Send + 'static) -> anyhow::Result<()> {
+    let (ready, registered) = std::sync::mpsc::sync_channel(1);
+    let text = hotkey.to_string();
+    std::thread::spawn(move || unsafe {
+        if let Err(e) = RegisterHotKey(None, 1, hotkey.modifiers, hotkey.vk) {
+            let _ = ready.send(Err(e));
+            return;
+        }
+        let _ = ready.send(Ok(()));
+        let mut msg = MSG::default();
+        while GetMessageW(&mut msg, None, 0, 0).as_bool() {
+            if msg.message == WM_HOTKEY {
+                on_press();
+            }
+        }
+    });
+    registered
+        .recv()
+        .map_err(|_| anyhow::anyhow!("Hotkey thread exited during setup"))?
+        .map_err(|e| anyhow::anyhow!("Failed to register hotkey {}: {}", text, e))
+}
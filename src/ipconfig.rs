@@ -0,0 +1,160 @@
+//! IP configuration of an interface (addresses, gateways, DNS servers) via GetAdaptersAddresses
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use serde::{Deserialize, Serialize};
+use windows::Win32::Foundation::{ERROR_BUFFER_OVERFLOW, NO_ERROR};
+use windows::Win32::NetworkManagement::IpHelper::{
+    ConvertInterfaceGuidToLuid, GetAdaptersAddresses, GAA_FLAG_INCLUDE_GATEWAYS,
+    GAA_FLAG_SKIP_ANYCAST, GAA_FLAG_SKIP_MULTICAST, IF_TYPE_ETHERNET_CSMACD,
+    IP_ADAPTER_ADDRESSES_LH,
+};
+use windows::Win32::NetworkManagement::Ndis::IfOperStatusUp;
+use windows::Win32::NetworkManagement::Ndis::NET_LUID_LH;
+use windows::Win32::Networking::WinSock::{
+    AF_INET, AF_INET6, AF_UNSPEC, SOCKADDR_IN, SOCKADDR_IN6, SOCKET_ADDRESS,
+};
+
+/// Addresses, gateways and DNS servers of one interface
+#[derive(Clone, Debug, Default, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct IpConfig {
+    /// Unicast addresses with prefix length, e.g. 192.168.1.20/24
+    pub addresses: Vec<String>,
+    pub gateways: Vec<String>,
+    pub dns_servers: Vec<String>,
+}
+
+impl IpConfig {
+    /// Common misconfigurations: APIPA only (no DHCP answer), no gateway, no DNS
+    pub fn problems(&self) -> Vec<&'static str> {
+        let mut problems = Vec::new();
+        let v4: Vec<&String> = self.addresses.iter().filter(|a| !a.contains(':')).collect();
+        if !v4.is_empty() && v4.iter().all(|a| a.starts_with("169.254.")) {
+            problems.push("only a link-local (APIPA) IPv4 address: DHCP did not answer");
+        }
+        if self.gateways.is_empty() {
+            problems.push("no default gateway");
+        }
+        if self.dns_servers.is_empty() {
+            problems.push("no DNS servers");
+        }
+        problems
+    }
+
+    /// Log the configuration, plus a warning per detected problem
+    pub fn log(&self) {
+        tracing::info!(
+            "IP: {} gateway: {} DNS: {}",
+            list_or_none(&self.addresses),
+            list_or_none(&self.gateways),
+            list_or_none(&self.dns_servers)
+        );
+        for problem in self.problems() {
+            tracing::warn!("IP configuration: {}", problem);
+        }
+    }
+}
+
+fn list_or_none(items: &[String]) -> String {
+    if items.is_empty() {
+        "none".to_string()
+    } else {
+        items.join(", ")
+    }
+}
+
+/// IP configuration of the adapter with this GUID (e.g. a WLAN interface GUID)
+pub fn for_interface(guid: &windows::core::GUID) -> Option<IpConfig> {
+    let mut luid = NET_LUID_LH::default();
+    if unsafe { ConvertInterfaceGuidToLuid(guid, &mut luid) } != NO_ERROR {
+        return None;
+    }
+    find_adapter(|a| unsafe { a.Luid.Value == luid.Value }).map(|(_, config)| config)
+}
+
+/// First Ethernet adapter that is up with a default gateway: (friendly name, IP configuration).
+/// Virtual switches without a gateway (Hyper-V, WSL) are not counted.
+pub fn ethernet_up() -> Option<(String, IpConfig)> {
+    find_adapter(|a| a.IfType == IF_TYPE_ETHERNET_CSMACD && a.OperStatus == IfOperStatusUp)
+        .filter(|(_, config)| !config.gateways.is_empty())
+}
+
+/// Name and IP configuration of the first adapter matching `pred`
+fn find_adapter(pred: impl Fn(&IP_ADAPTER_ADDRESSES_LH) -> bool) -> Option<(String, IpConfig)> {
+    let flags = GAA_FLAG_INCLUDE_GATEWAYS | GAA_FLAG_SKIP_ANYCAST | GAA_FLAG_SKIP_MULTICAST;
+    // Recommended starting size; u64 backing keeps the structures aligned
+    let mut size = 15_000u32;
+    let mut buf: Vec<u64>;
+    loop {
+        buf = vec![0u64; (size as usize).div_ceil(8)];
+        let status = unsafe {
+            GetAdaptersAddresses(
+                AF_UNSPEC.0 as u32,
+                flags,
+                None,
+                Some(buf.as_mut_ptr().cast()),
+                &mut size,
+            )
+        };
+        match status {
+            s if s == NO_ERROR.0 => break,
+            s if s == ERROR_BUFFER_OVERFLOW.0 => continue,
+            _ => return None,
+        }
+    }
+
+    let mut adapter = buf.as_ptr().cast::<IP_ADAPTER_ADDRESSES_LH>();
+    while let Some(a) = unsafe { adapter.as_ref() } {
+        if pred(a) {
+            let name = unsafe { a.FriendlyName.to_string() }.unwrap_or_default();
+            return Some((name, unsafe { adapter_config(a) }));
+        }
+        adapter = a.Next;
+    }
+    None
+}
+
+/// Addresses, gateways and DNS servers of one GetAdaptersAddresses entry
+unsafe fn adapter_config(a: &IP_ADAPTER_ADDRESSES_LH) -> IpConfig {
+    let mut config = IpConfig::default();
+    let mut unicast = a.FirstUnicastAddress;
+    while let Some(u) = unicast.as_ref() {
+        if let Some(ip) = socket_address_ip(&u.Address) {
+            config
+                .addresses
+                .push(format!("{}/{}", ip, u.OnLinkPrefixLength));
+        }
+        unicast = u.Next;
+    }
+    let mut gateway = a.FirstGatewayAddress;
+    while let Some(g) = gateway.as_ref() {
+        if let Some(ip) = socket_address_ip(&g.Address) {
+            config.gateways.push(ip.to_string());
+        }
+        gateway = g.Next;
+    }
+    let mut dns = a.FirstDnsServerAddress;
+    while let Some(d) = dns.as_ref() {
+        if let Some(ip) = socket_address_ip(&d.Address) {
+            config.dns_servers.push(ip.to_string());
+        }
+        dns = d.Next;
+    }
+    config
+}
+
+/// IPv4/IPv6 address in a SOCKET_ADDRESS (None for other families)
+unsafe fn socket_address_ip(addr: &SOCKET_ADDRESS) -> Option<IpAddr> {
+    let sa = addr.lpSockaddr.as_ref()?;
+    if sa.sa_family == AF_INET {
+        let v4 = &*addr.lpSockaddr.cast::<SOCKADDR_IN>();
+        Some(IpAddr::V4(Ipv4Addr::from(
+            v4.sin_addr.S_un.S_addr.to_ne_bytes(),
+        )))
+    } else if sa.sa_family == AF_INET6 {
+        let v6 = &*addr.lpSockaddr.cast::<SOCKADDR_IN6>();
+        Some(IpAddr::V6(Ipv6Addr::from(v6.sin6_addr.u.Byte)))
+    } else {
+        None
+    }
+}
@@ -0,0 +1,218 @@
+//! Remote log sink (`--log-sink tcp://host:port` / `udp://host:port`): JSON lines sent by a
+//! background thread through a bounded channel. Lines are dropped when the channel is full or
+//! the collector is unreachable, so logging never blocks the probe or recovery.
+
+use std::io::{self, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::time::{Duration, Instant};
+
+use tracing_subscriber::fmt::MakeWriter;
+
+/// Lines buffered for the sender thread before new ones are dropped
+const SINK_QUEUE_LINES: usize = 1024;
+
+/// Minimum time between TCP reconnect attempts
+const TCP_RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// TCP connect / write timeout
+const TCP_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Lines handed to the sender thread and not yet sent (for `drain`)
+static QUEUED: AtomicUsize = AtomicUsize::new(0);
+
+/// Wait up to `timeout` for the sender thread to send the queued lines, e.g. before the
+/// process dies on a panic. Returns at once when no sink is running.
+pub fn drain(timeout: Duration) {
+    let start = Instant::now();
+    while QUEUED.load(Ordering::Acquire) > 0 && start.elapsed() < timeout {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Parsed `--log-sink` target
+#[derive(Clone, Debug)]
+pub enum SinkTarget {
+    Tcp(String),
+    Udp(String),
+}
+
+impl FromStr for SinkTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, addr) = s
+            .split_once("://")
+            .ok_or_else(|| format!("expected tcp://host:port or udp://host:port, got \"{}\"", s))?;
+        check_host_port(addr).map_err(|e| format!("{} in \"{}\"", e, s))?;
+        match scheme.to_ascii_lowercase().as_str() {
+            "tcp" => Ok(Self::Tcp(addr.to_string())),
+            "udp" => Ok(Self::Udp(addr.to_string())),
+            other => Err(format!("unsupported log sink scheme \"{}\"", other)),
+        }
+    }
+}
+
+/// Validate `host:port`. IPv6 literals must be bracketed, e.g. `[2606:4700:4700::1111]:443`,
+/// since a bare `::1:514` cannot be split into address and port unambiguously.
+fn check_host_port(addr: &str) -> Result<(), String> {
+    if addr.parse::<SocketAddr>().is_ok() {
+        return Ok(());
+    }
+    let (host, port) = addr
+        .rsplit_once(':')
+        .ok_or_else(|| "missing port".to_string())?;
+    if host.contains(':') || host.starts_with('[') {
+        return Err("IPv6 addresses must be written as [addr]:port".to_string());
+    }
+    if host.is_empty() {
+        return Err("missing host".to_string());
+    }
+    port.parse::<u16>()
+        .map_err(|_| format!("invalid port \"{}\"", port))?;
+    Ok(())
+}
+
+/// MakeWriter handing each formatted log line to the sender thread
+#[derive(Clone)]
+pub struct LogSink {
+    tx: SyncSender<Vec<u8>>,
+}
+
+impl LogSink {
+    /// Start the sender thread for `target`
+    pub fn spawn(target: SinkTarget) -> Self {
+        let (tx, rx) = sync_channel(SINK_QUEUE_LINES);
+        std::thread::Builder::new()
+            .name("log-sink".to_string())
+            .spawn(move || match target {
+                SinkTarget::Tcp(addr) => run_tcp(&addr, rx),
+                SinkTarget::Udp(addr) => run_udp(&addr, rx),
+            })
+            .expect("failed to spawn log sink thread");
+        Self { tx }
+    }
+}
+
+impl Write for LogSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Full queue or dead thread: drop the line rather than block the caller
+        // Count before sending: the sender thread may take the line before try_send returns
+        QUEUED.fetch_add(1, Ordering::AcqRel);
+        if self.tx.try_send(buf.to_vec()).is_err() {
+            QUEUED.fetch_sub(1, Ordering::AcqRel);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogSink {
+    type Writer = LogSink;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+/// Send lines over one TCP connection, reconnecting (rate-limited) after errors.
+/// Errors cannot be logged here without feeding back into the sink, so they are dropped.
+fn run_tcp(addr: &str, rx: Receiver<Vec<u8>>) {
+    let mut stream: Option<TcpStream> = None;
+    let mut last_attempt: Option<Instant> = None;
+    for line in rx {
+        if stream.is_none() && last_attempt.is_none_or(|t| t.elapsed() >= TCP_RECONNECT_INTERVAL) {
+            last_attempt = Some(Instant::now());
+            stream = addr
+                .to_socket_addrs()
+                .ok()
+                .and_then(|mut addrs| addrs.next())
+                .and_then(|sa| TcpStream::connect_timeout(&sa, TCP_TIMEOUT).ok());
+            if let Some(ref s) = stream {
+                let _ = s.set_write_timeout(Some(TCP_TIMEOUT));
+            }
+        }
+        if let Some(ref mut s) = stream {
+            if s.write_all(&line).is_err() {
+                stream = None;
+            }
+        }
+        QUEUED.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Send each line as one UDP datagram, from a socket of the target's address family
+/// (resolved on first use, retried while resolution fails)
+fn run_udp(addr: &str, rx: Receiver<Vec<u8>>) {
+    let mut target: Option<(UdpSocket, SocketAddr)> = None;
+    for line in rx {
+        if target.is_none() {
+            target = addr
+                .to_socket_addrs()
+                .ok()
+                .and_then(|mut addrs| addrs.next())
+                .and_then(|sa| {
+                    let local = if sa.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" };
+                    UdpSocket::bind(local).ok().map(|socket| (socket, sa))
+                });
+        }
+        if let Some((ref socket, sa)) = target {
+            let _ = socket.send_to(&line, sa);
+        }
+        QUEUED.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_ipv4_target() {
+        assert!(matches!(
+            "tcp://1.1.1.1:443".parse::<SinkTarget>(),
+            Ok(SinkTarget::Tcp(addr)) if addr == "1.1.1.1:443"
+        ));
+    }
+
+    #[test]
+    fn accepts_bracketed_ipv6_target() {
+        assert!(matches!(
+            "udp://[2606:4700:4700::1111]:443".parse::<SinkTarget>(),
+            Ok(SinkTarget::Udp(addr)) if addr == "[2606:4700:4700::1111]:443"
+        ));
+    }
+
+    #[test]
+    fn accepts_hostname_target() {
+        assert!(matches!(
+            "TCP://logs.example.com:5140".parse::<SinkTarget>(),
+            Ok(SinkTarget::Tcp(addr)) if addr == "logs.example.com:5140"
+        ));
+    }
+
+    #[test]
+    fn rejects_bare_ipv6() {
+        let err = "udp://::1:514".parse::<SinkTarget>().unwrap_err();
+        assert!(err.contains("[addr]:port"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_missing_port() {
+        let err = "tcp://logs.example.com".parse::<SinkTarget>().unwrap_err();
+        assert!(err.contains("missing port"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_bad_port() {
+        let err = "tcp://logs.example.com:70000"
+            .parse::<SinkTarget>()
+            .unwrap_err();
+        assert!(err.contains("invalid port"), "{}", err);
+    }
+}
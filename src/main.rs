@@ -2,15 +2,50 @@
 
 mod adapter;
 mod admin;
+mod alert;
+mod csvlog;
+mod detach;
+mod eap;
+mod escalate;
+mod hooks;
+mod hotkey;
+mod ipconfig;
+mod logsink;
 mod network;
+mod nlm;
+mod policy;
+mod power;
+mod probes;
 mod radio;
+mod registry;
+mod route;
+mod schedule;
+mod state;
+mod stats;
+mod task;
+mod timing;
+mod tray;
+mod types;
+mod version;
 mod wlan;
 
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
 
-use clap::Parser;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser};
+use timing::LoopState;
+use tokio::sync::Notify;
 use tokio::time::{sleep, Duration};
-use wlan::ConnectStrategy;
+use tokio_util::sync::CancellationToken;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use wlan::{ConnectStrategy, RecoveryOptions};
 
 #[derive(Parser, Debug)]
 #[command(
@@ -19,33 +54,633 @@ use wlan::ConnectStrategy;
     long_about = "Periodically checks network (NCSI). If unreachable, turns on Wi-Fi radio and tries saved Wi-Fi profiles until restored or all tried."
 )]
 struct Cli {
+    /// Print version, build info and the Windows build, then exit
+    #[arg(long, short = 'V')]
+    pub version: bool,
+
     /// Run once: check network once, try recovery once if down, then exit (no loop)
     #[arg(long, short = '1', alias = "single")]
     pub once: bool,
 
     /// Check interval in seconds
-    #[arg(long, default_value_t = 60)]
+    #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u64).range(1..))]
     pub interval: u64,
 
-    /// NCSI probe URL
+    /// Exit with an error if Wi-Fi is blocked by policy (default: warn and keep going)
+    #[arg(long)]
+    pub fail_on_policy_block: bool,
+
+    /// Exit after this many seconds: code 0 if the network is up, 2 if down
+    #[arg(long, value_name = "SECS")]
+    pub max_runtime: Option<u64>,
+
+    /// NCSI probe URL; repeat for several endpoints (the fastest is used, others as fallback)
     #[arg(long, default_value = network::DEFAULT_NCSI_URL)]
-    pub ncsi_url: String,
+    pub ncsi_url: Vec<String>,
+
+    /// Probe the same URL Windows NCSI uses (from the registry) instead of --ncsi-url
+    #[arg(long, conflicts_with = "ncsi_url")]
+    pub use_windows_ncsi: bool,
 
-    /// NCSI request timeout in seconds
+    /// NCSI request timeout in seconds (see --ncsi-timeout-ms for finer steps)
     #[arg(long, default_value_t = network::DEFAULT_NCSI_TIMEOUT_SECS)]
     pub ncsi_timeout: u64,
 
     /// Try all saved Wi-Fi profiles (no \"visible only\" filter; default is scan-only)
-    #[arg(long)]
+    #[arg(long, conflicts_with = "profiles")]
     pub all: bool,
 
     /// Only try these saved profile names; multiple or comma-separated
     /// e.g. --profiles Home --profiles Office or --profiles "Home,Office"
     #[arg(long, value_delimiter(','), num_args = 1..)]
     pub profiles: Option<Vec<String>>,
+
+    /// Only run recovery inside this local-time window, e.g. "08:00-22:00"
+    /// (may cross midnight); probing continues outside it
+    #[arg(long, value_name = "HH:MM-HH:MM")]
+    pub active_hours: Option<schedule::ActiveHours>,
+
+    /// Internal endpoint (e.g. behind a VPN) probed after NCSI passes; failures are logged
+    /// separately since Wi-Fi recovery cannot fix them. Uses the system proxy settings.
+    #[arg(long, value_name = "URL")]
+    pub internal_url: Option<String>,
+
+    /// Treat "internet up, internal endpoint unreachable" as down (hooks, exit code);
+    /// Wi-Fi recovery is still skipped
+    #[arg(long, requires = "internal_url")]
+    pub require_internal: bool,
+
+    /// Keep the machine on this saved profile: every iteration, reconnect it if Wi-Fi is
+    /// connected elsewhere or disconnected, regardless of the probe result
+    #[arg(long, value_name = "NAME")]
+    pub pin_profile: Option<String>,
+
+    /// Probe without any proxy, so the verdict reflects direct connectivity
+    /// (default: system proxy settings)
+    #[arg(long, conflicts_with = "proxy")]
+    pub no_proxy: bool,
+
+    /// Send probes through this proxy, e.g. http://proxy.corp:8080
+    #[arg(long, value_name = "URL")]
+    pub proxy: Option<String>,
+
+    /// Play the system error sound when a recovery round fails to restore the network
+    #[arg(long)]
+    pub beep_on_fail: bool,
+
+    /// Abandon a recovery round that runs longer than this many seconds
+    /// (guards against a hung WLAN/WinRT call)
+    #[arg(long, value_name = "SECS", default_value_t = 900)]
+    pub round_hard_timeout: u64,
+
+    /// Retry a failed probe request this many times before reporting down (0 = single shot)
+    #[arg(long, default_value_t = 0)]
+    pub probe_retries: u32,
+
+    /// Base delay between probe retries in milliseconds (plus up to 50% random jitter)
+    #[arg(long, default_value_t = 500)]
+    pub probe_retry_delay_ms: u64,
+
+    /// Reuse a probe result for this many milliseconds (0 = disabled)
+    #[arg(long, default_value_t = 0)]
+    pub probe_cache_ms: u64,
+
+    /// When the probe fails, retry it resolving the NCSI host via this DNS server
+    /// (diagnoses a broken system DNS)
+    #[arg(long, value_name = "IP")]
+    pub probe_dns: Option<IpAddr>,
+
+    /// State file written every loop iteration and read by --status
+    /// (default: %ProgramData%\network-watchdog\state.json)
+    #[arg(long, value_name = "PATH")]
+    pub state_file: Option<PathBuf>,
+
+    /// Print the state written by a running loop as one line, e.g.
+    /// "UP ssid=Home signal=82% since=12m", then exit (no probing, no admin needed)
+    #[arg(long)]
+    pub status: bool,
+
+    /// With --status: print the state as JSON
+    #[arg(long, requires = "status")]
+    pub json: bool,
+
+    /// Also send JSON log lines to a remote collector: tcp://host:port or udp://host:port
+    #[arg(long, value_name = "URL")]
+    pub log_sink: Option<logsink::SinkTarget>,
+
+    /// Print the stored XML of this saved profile and exit (key material masked)
+    #[arg(long, value_name = "NAME")]
+    pub print_profile_xml: Option<String>,
+
+    /// With --print-profile-xml: show the key in plain text
+    #[arg(long, requires = "print_profile_xml")]
+    pub show_key: bool,
+
+    /// Print every visible BSS (BSSID, RSSI, channel, PHY type) per interface and SSID as JSON
+    /// and exit
+    #[arg(long)]
+    pub list_bss: bool,
+
+    /// Persist per-profile attempt/success statistics to this JSON file
+    #[arg(long, value_name = "PATH")]
+    pub stats_file: Option<PathBuf>,
+
+    /// Shell command to run when the network goes down (before Wi-Fi recovery)
+    #[arg(long, value_name = "CMD")]
+    pub on_down: Option<String>,
+
+    /// Shell command to run when the network is restored
+    /// (WATCHDOG_SSID / WATCHDOG_PROFILE are set when known)
+    #[arg(long, value_name = "CMD")]
+    pub on_restored: Option<String>,
+
+    /// Hook command timeout in seconds
+    #[arg(long, default_value_t = hooks::DEFAULT_HOOK_TIMEOUT_SECS)]
+    pub hook_timeout: u64,
+
+    /// After connecting, reconnect to a noticeably stronger access point (BSSID) of the same SSID
+    #[arg(long)]
+    pub roam_to_best: bool,
+
+    /// Connect with session-only (temporary) copies of saved profiles, leaving the
+    /// preferred-network list and its auto-connect order untouched
+    #[arg(long)]
+    pub temporary: bool,
+
+    /// User name for 802.1X (PEAP-MSCHAPv2) profiles, optionally DOMAIN\user
+    #[arg(long, value_name = "USER", requires = "eap_pass")]
+    pub eap_user: Option<String>,
+
+    /// Password for 802.1X profiles (never logged)
+    #[arg(long, value_name = "PASS", requires = "eap_user", env = admin::EAP_PASS_ENV, hide_env_values = true)]
+    pub eap_pass: Option<String>,
+
+    /// WlanConnect flags for a profile, e.g. "Office=hidden,ignore-privacy"; repeatable.
+    /// Flags: hidden, ignore-privacy, adhoc-join-only, eapol-passthrough
+    #[arg(long, value_name = "PROFILE=FLAGS")]
+    pub connect_flags: Vec<wlan::ProfileConnectFlags>,
+
+    /// Don't pick profiles: ask Windows to auto-connect by its own priority order and wait
+    /// (same as `--connect-mode auto`)
+    #[arg(long)]
+    pub strict_autoconnect: bool,
+
+    /// Count the handshake states (discovering, associating, authenticating) as connected (for
+    /// drivers that linger there)
+    #[arg(long)]
+    pub accept_associating: bool,
+
+    /// Report the first COUNT probes as failed to exercise recovery (connects are real)
+    #[arg(long, value_name = "COUNT", default_value_t = 0)]
+    pub simulate_down: u32,
+
+    /// Keep trying profiles when the network is up but the default route uses another adapter
+    #[arg(long)]
+    pub require_wifi_route: bool,
+
+    /// Try profiles on all Wi-Fi interfaces at once (some drivers misbehave with this)
+    #[arg(long)]
+    pub concurrent_interfaces: bool,
+
+    /// While up: if signal quality stays below PERCENT and a stronger saved network is
+    /// visible, switch to it
+    #[arg(long, value_name = "PERCENT", value_parser = clap::value_parser!(u32).range(1..=100))]
+    pub roam_floor: Option<u32>,
+
+    /// Observe only: when down, scan and log saved networks in range, but never turn on the
+    /// radio or connect
+    #[arg(long, conflicts_with_all = ["pin_profile", "roam_floor"])]
+    pub monitor_only: bool,
+
+    /// Don't re-scan an interface within this many seconds; reuse the last scan results
+    #[arg(long, value_name = "SECS", default_value_t = 10)]
+    pub scan_min_interval: u64,
+
+    /// Start recovery by disconnecting and reconnecting the current network once
+    #[arg(long)]
+    pub reconnect_current_first: bool,
+
+    /// HTTP method for probes; head checks only the status code (falls back to GET on 405);
+    /// wininet takes Windows' own connectivity level when it reports internet access and
+    /// confirms anything else with GET
+    #[arg(long, value_enum, default_value_t = network::ProbeMethod::Get)]
+    pub probe_method: network::ProbeMethod,
+
+    /// Re-launch in the background (no console window) and return immediately
+    #[arg(long, conflicts_with_all = ["once", "status", "print_profile_xml"])]
+    pub detach: bool,
+
+    /// Pause Windows auto-connect while recovering so it doesn't fight our connects
+    #[arg(long)]
+    pub suspend_autoconnect: bool,
+
+    /// When a probe fails, also try a raw IP (no DNS) and log whether DNS is the likely cause
+    #[arg(long)]
+    pub diagnose_dns: bool,
+
+    /// Abandon Wi-Fi recovery (success) as soon as an Ethernet adapter comes up with connectivity
+    #[arg(long)]
+    pub ethernet_aware: bool,
+
+    /// When not admin, run anyway without elevating, skipping steps that need admin rights
+    #[arg(long)]
+    pub best_effort_noadmin: bool,
+
+    /// Seconds to wait for a WLAN interface to appear after enabling the adapter
+    #[arg(long, value_name = "SECS", default_value_t = 3, value_parser = clap::value_parser!(u64).range(1..))]
+    pub adapter_wait: u64,
+
+    /// Send probes from the Wi-Fi adapter's IPv4 address, so a VPN split tunnel can't make
+    /// the verdict "up" while Wi-Fi is down
+    #[arg(long)]
+    pub probe_wifi_source: bool,
+
+    /// Also treat networks seen in scans within the last SECS as visible (0: off)
+    #[arg(long, value_name = "SECS", default_value_t = 0)]
+    pub scan_memory: u64,
+
+    /// Order to try candidate profiles in: signal (strongest first) or reverse
+    #[arg(long, value_enum, default_value_t = wlan::ProfileOrder::Signal)]
+    pub profile_order: wlan::ProfileOrder,
+
+    /// Ask Windows' Network List Manager first; skip the HTTP probe when it reports internet
+    #[arg(long, conflicts_with = "probe_wifi_source")]
+    pub use_nlm: bool,
+
+    /// Minimum milliseconds between connect requests, to spare fragile drivers (0: no pacing)
+    #[arg(long, value_name = "MS", default_value_t = 0)]
+    pub connect_min_gap_ms: u64,
+
+    /// Connectivity checks to run: http, dns, tcp, ping; repeatable or comma-separated
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = [probes::ProbeProtocol::Http])]
+    pub probe_protocol: Vec<probes::ProbeProtocol>,
+
+    /// Combine --probe-protocol results: all must pass, or any
+    #[arg(long, value_enum, default_value_t = probes::ProbeCombine::All)]
+    pub probe_combine: probes::ProbeCombine,
+
+    /// Show a notification-area icon with the status and a Check now / Pause / Quit menu
+    #[arg(long)]
+    pub tray: bool,
+
+    /// Global hotkey that runs a check (and recovery) right away, e.g. "Ctrl+Alt+W"
+    #[arg(long, value_name = "KEYS")]
+    pub check_hotkey: Option<hotkey::Hotkey>,
+
+    /// Number of recent probe results kept for --down-threshold/--up-threshold
+    #[arg(long, value_name = "K", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub probe_window: u32,
+
+    /// Treat the network as down only once this many of the last K probes failed
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub down_threshold: u32,
+
+    /// Once down, treat the network as up again only after this many of the last K probes passed
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub up_threshold: u32,
+
+    /// Keep the loop cadence: cap --ncsi-timeout at --interval and give up on a loop probe
+    /// (counted as failed) once it has taken a whole interval, retries included
+    #[arg(long)]
+    pub cap_probe_to_interval: bool,
+
+    /// Escalation ladder tried before the regular round, lightest first; stops at the first
+    /// step that restores the network
+    #[arg(long, value_delimiter = ',', value_name = "STEPS")]
+    pub escalate: Vec<escalate::Rung>,
+
+    /// Write the current Unix time to this file at the top of every loop iteration and around
+    /// recovery rounds, for an external supervisor to detect a stuck watchdog
+    #[arg(long, value_name = "PATH")]
+    pub heartbeat_file: Option<PathBuf>,
+
+    /// Bind probes to the Wi-Fi adapter's IPv4 address so they bypass a VPN holding the
+    /// default route; while Wi-Fi has no address, probe unbound
+    #[arg(long, conflicts_with_all = ["probe_wifi_source", "use_nlm"])]
+    pub probe_bind_wifi: bool,
+
+    /// Response header the probe server must send, e.g. "X-Health: ok" (a proxy answering 200
+    /// with its own content lacks it)
+    #[arg(long, value_name = "NAME: VALUE")]
+    pub probe_expect_header: Option<network::ExpectedHeader>,
+
+    /// Connect each saved profile in range in turn, probe it, print a ranked report and exit;
+    /// the original connection is restored afterwards
+    #[arg(long, conflicts_with_all = ["once", "status", "detach", "monitor_only"])]
+    pub survey: bool,
+
+    /// What a successful probe response looks like: ncsi (200 + Microsoft sentinel body),
+    /// gen204 (exactly 204, empty body), custom (any 2xx); auto picks gen204 for
+    /// generate_204 URLs and custom otherwise
+    #[arg(long, value_enum, default_value_t = network::ProbeStyle::Auto)]
+    pub probe_style: network::ProbeStyle,
+
+    /// Set on the gsudo relaunch: fail fast if this instance is still not admin
+    #[arg(long, hide = true)]
+    pub elevated_child: bool,
+
+    /// WlanScan calls per scan (a couple of seconds apart) before reading the merged
+    /// available-network list; more passes catch networks a single scan misses
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=10))]
+    pub scan_passes: u32,
+
+    /// What counts as healthy: lenient checks the response against --probe-style; strict also
+    /// requires GET with no redirect, a sentinel body and a fast round-trip
+    #[arg(long, value_enum, default_value_t = network::HealthLevel::Lenient)]
+    pub health: network::HealthLevel,
+
+    /// --health strict: probes slower than this count as down
+    #[arg(long, value_name = "MS", default_value_t = network::DEFAULT_HEALTH_MAX_LATENCY_MS)]
+    pub health_max_latency_ms: u64,
+
+    /// Create a Scheduled Task that runs the watchdog with the other arguments given here,
+    /// then exit
+    #[arg(long, conflicts_with_all = ["uninstall_task", "status", "detach", "survey", "list_bss"])]
+    pub install_task: bool,
+
+    /// Remove the Scheduled Task created by --install-task, then exit
+    #[arg(long)]
+    pub uninstall_task: bool,
+
+    /// When the --install-task task starts: at logon (current user) or at boot (LocalSystem)
+    #[arg(long, value_enum, default_value_t = task::TaskTrigger::Logon, requires = "install_task")]
+    pub task_trigger: task::TaskTrigger,
+
+    /// Only run recovery on this power source (probing and logging continue); an unknown
+    /// power state counts as a match
+    #[arg(long, value_enum, default_value_t = power::RecoverOn::Always)]
+    pub recover_on: power::RecoverOn,
+
+    /// Give up on a profile as soon as the interface falls back to disconnected after
+    /// associating/authenticating (rejected, e.g. wrong password) instead of waiting out the
+    /// 30 s connect timeout
+    #[arg(long, value_name = "BOOL", default_value_t = true, action = clap::ArgAction::Set)]
+    pub fail_fast_on_disconnect: bool,
+
+    /// Seconds between checks while the network is down (default: --interval)
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub down_interval: Option<u64>,
+
+    /// Multiply the down interval by this after each further failed recovery round in a row,
+    /// up to --backoff-max; reset once the network is up (1: no backoff)
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=10))]
+    pub backoff_factor: u32,
+
+    /// Longest sleep the --backoff-factor backoff grows to, in seconds
+    #[arg(long, value_name = "SECS", default_value_t = timing::DEFAULT_BACKOFF_MAX_SECS)]
+    pub backoff_max: u64,
+
+    /// Add up to this many milliseconds of random delay to every sleep, so several machines
+    /// on one network don't probe and recover in lockstep
+    #[arg(long, value_name = "MS", default_value_t = 0)]
+    pub jitter_ms: u64,
+
+    /// After a recovery round restores the network, wait at least this many seconds before
+    /// the next check, to let the link settle
+    #[arg(long, value_name = "SECS", default_value_t = 0)]
+    pub cooldown: u64,
+
+    /// Never run recovery while connected to this SSID (exact, case-sensitive), e.g. a guest
+    /// network with a captive portal; the probe still runs and is logged. Repeatable
+    #[arg(long, value_name = "NAME")]
+    pub skip_recovery_if_ssid: Vec<String>,
+
+    /// Print the JSON Schema of the state file / `--status --json` output and exit
+    #[arg(long)]
+    pub print_schema: bool,
+
+    /// Second-opinion endpoint: when the --ncsi-url probe fails, request this once before
+    /// counting the network as down, so an outage of the NCSI host alone triggers no recovery
+    #[arg(long, value_name = "URL")]
+    pub backup_ncsi_url: Option<String>,
+
+    /// Only connect to access points in this band: each profile's connect is restricted to the
+    /// visible BSSIDs of its SSID in the band, and profiles with none are skipped
+    #[arg(long, value_enum, value_name = "GHZ")]
+    pub band_only: Option<wlan::Band>,
+
+    /// Append a CSV row per probe (timestamp, up/down, latency, SSID, signal, IP) to this file
+    #[arg(long, value_name = "PATH")]
+    pub csv_log: Option<PathBuf>,
+
+    /// Start a new --csv-log file each day (`daily`) or at a size (e.g. `10MB`); the old one is
+    /// renamed with the date or time appended
+    #[arg(long, value_name = "WHEN", requires = "csv_log")]
+    pub csv_rotate: Option<csvlog::CsvRotate>,
+
+    /// How recovery connects: profile names each candidate saved profile; auto only asks
+    /// Windows to reconnect by its own preferred-network order (radio is still turned on first)
+    #[arg(long, value_enum, default_value_t = wlan::ConnectMode::Profile)]
+    pub connect_mode: wlan::ConnectMode,
+
+    /// If a recovery round fails, reconnect to the profile that was connected when it started,
+    /// so recovery never leaves the adapter worse off (disconnected) than it found it
+    #[arg(long)]
+    pub restore_on_fail: bool,
+
+    /// NCSI request timeout in milliseconds, for sub-second probes (e.g. 800 on a fast LAN);
+    /// replaces --ncsi-timeout
+    #[arg(long, value_name = "MS", conflicts_with = "ncsi_timeout", value_parser = clap::value_parser!(u64).range(1..))]
+    pub ncsi_timeout_ms: Option<u64>,
+
+    /// User name for Basic authentication to --proxy
+    #[arg(long, value_name = "USER", requires_all = ["proxy", "proxy_pass"])]
+    pub proxy_user: Option<String>,
+
+    /// Password for --proxy Basic authentication (never logged)
+    #[arg(long, value_name = "PASS", requires = "proxy_user", env = admin::PROXY_PASS_ENV, hide_env_values = true)]
+    pub proxy_pass: Option<String>,
+
+    /// Run at most this many probe requests (fallback endpoints, --probe-protocol checks) at
+    /// once (default: no limit)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    pub probe_concurrency: Option<u64>,
+
+    /// Also write the log, as plain text, to this file (appended); --detach defaults it to
+    /// %ProgramData%\network-watchdog\watchdog.log
+    #[arg(long, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
 }
 
 impl Cli {
+    /// Reject contradictory option combinations that clap attributes can't express, and
+    /// return warnings for redundant ones (logged once tracing is up)
+    fn validate(&self, matches: &ArgMatches) -> Result<Vec<String>, clap::Error> {
+        let explicit = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+        let conflict = |msg: &str| Cli::command().error(ErrorKind::ArgumentConflict, msg);
+
+        if self.once && self.max_runtime.is_some() {
+            return Err(conflict(
+                "--once exits after one check; --max-runtime needs the loop",
+            ));
+        }
+        if self.down_threshold > self.probe_window || self.up_threshold > self.probe_window {
+            return Err(conflict(
+                "--down-threshold and --up-threshold cannot exceed --probe-window",
+            ));
+        }
+        if self.use_nlm && self.probe_method == network::ProbeMethod::Wininet {
+            return Err(conflict(
+                "--use-nlm and --probe-method wininet both take Windows' own verdict; use one",
+            ));
+        }
+        // Checked on the effective mode so `--strict-autoconnect` and `--connect-mode auto`
+        // reject the same flags
+        if self.connect_mode() == wlan::ConnectMode::Auto {
+            let mode = if self.strict_autoconnect {
+                "--strict-autoconnect"
+            } else {
+                "--connect-mode auto"
+            };
+            let clashing: Vec<&str> = [
+                ("all", "--all"),
+                ("profiles", "--profiles"),
+                ("temporary", "--temporary"),
+                ("roam_to_best", "--roam-to-best"),
+                ("concurrent_interfaces", "--concurrent-interfaces"),
+                ("suspend_autoconnect", "--suspend-autoconnect"),
+            ]
+            .into_iter()
+            .filter(|(id, _)| explicit(id))
+            .map(|(_, flag)| flag)
+            .collect();
+            if !clashing.is_empty() {
+                return Err(conflict(&format!(
+                    "{} lets Windows pick the network; {} cannot be used with it",
+                    mode,
+                    clashing.join(", ")
+                )));
+            }
+        }
+        if self.monitor_only && self.simulate_down > 0 {
+            return Err(conflict(
+                "--simulate-down exercises recovery, which --monitor-only never runs",
+            ));
+        }
+
+        let mut warnings = Vec::new();
+        if self.once && explicit("interval") {
+            warnings.push("--interval has no effect with --once".to_string());
+        }
+        if self.once && self.roam_floor.is_some() {
+            warnings.push(format!(
+                "--roam-floor needs {} consecutive checks and never triggers with --once",
+                ROAM_FLOOR_CHECKS
+            ));
+        }
+        if !self.once
+            && !self.cap_probe_to_interval
+            && self.ncsi_timeout() > Duration::from_secs(self.interval)
+        {
+            warnings.push(format!(
+                "NCSI timeout {} ms exceeds --interval {} s: slow probes will stretch the loop \
+                 (see --cap-probe-to-interval)",
+                self.ncsi_timeout().as_millis(),
+                self.interval
+            ));
+        }
+        if explicit("hook_timeout") && self.on_down.is_none() && self.on_restored.is_none() {
+            warnings.push("--hook-timeout is set but no --on-down/--on-restored hook".to_string());
+        }
+        if explicit("probe_retry_delay_ms") && self.probe_retries == 0 {
+            warnings
+                .push("--probe-retry-delay-ms has no effect without --probe-retries".to_string());
+        }
+        if self.health == network::HealthLevel::Strict {
+            if self.use_nlm {
+                warnings.push(
+                    "--use-nlm skips the probe while Windows reports internet, so --health \
+                     strict is only applied when it does not"
+                        .to_string(),
+                );
+            }
+            match self.probe_method {
+                network::ProbeMethod::Head => {
+                    warnings.push("--health strict always probes with GET".to_string())
+                }
+                network::ProbeMethod::Wininet => warnings.push(
+                    "--probe-method wininet takes Windows' \"internet access\" as is; --health \
+                     strict only applies when the HTTP probe runs (any other answer)"
+                        .to_string(),
+                ),
+                network::ProbeMethod::Get => {}
+            }
+        } else if explicit("health_max_latency_ms") {
+            warnings.push("--health-max-latency-ms only applies to --health strict".to_string());
+        }
+        if self.connect_mode() == wlan::ConnectMode::Auto && self.band_only.is_some() {
+            warnings.push(
+                "--band-only is not applied in auto connect mode (Windows picks the network)"
+                    .to_string(),
+            );
+        }
+        if self.connect_mode() == wlan::ConnectMode::Auto && self.eap_user.is_some() {
+            warnings.push("--eap-user/--eap-pass are not applied in auto connect mode".to_string());
+        }
+        if self.strict_autoconnect && explicit("connect_mode") {
+            warnings.push(
+                "--strict-autoconnect is the same as --connect-mode auto; it wins".to_string(),
+            );
+        }
+        if self.monitor_only {
+            let ignored: Vec<&str> = [
+                ("all", "--all"),
+                ("profiles", "--profiles"),
+                ("temporary", "--temporary"),
+                ("roam_to_best", "--roam-to-best"),
+                ("strict_autoconnect", "--strict-autoconnect"),
+                ("connect_mode", "--connect-mode"),
+                ("restore_on_fail", "--restore-on-fail"),
+                ("concurrent_interfaces", "--concurrent-interfaces"),
+                ("reconnect_current_first", "--reconnect-current-first"),
+                ("suspend_autoconnect", "--suspend-autoconnect"),
+                ("eap_user", "--eap-user"),
+                ("escalate", "--escalate"),
+                ("band_only", "--band-only"),
+                ("skip_recovery_if_ssid", "--skip-recovery-if-ssid"),
+            ]
+            .into_iter()
+            .filter(|(id, _)| explicit(id))
+            .map(|(_, flag)| flag)
+            .collect();
+            if !ignored.is_empty() {
+                warnings.push(format!(
+                    "{} ignored with --monitor-only (no recovery)",
+                    ignored.join(", ")
+                ));
+            }
+        }
+        Ok(warnings)
+    }
+
+    /// Loop timing from `--interval`, `--down-interval`, the backoff, jitter and cooldown
+    fn timing(&self) -> timing::TimingConfig {
+        timing::TimingConfig {
+            down_interval: Duration::from_secs(self.down_interval.unwrap_or(self.interval)),
+            backoff_factor: self.backoff_factor,
+            backoff_max: Duration::from_secs(self.backoff_max),
+            jitter: Duration::from_millis(self.jitter_ms),
+            cooldown: Duration::from_secs(self.cooldown),
+            ..timing::TimingConfig::new(self.interval)
+        }
+    }
+
+    /// `--ncsi-timeout-ms` if given, else `--ncsi-timeout`
+    fn ncsi_timeout(&self) -> Duration {
+        self.ncsi_timeout_ms
+            .map(Duration::from_millis)
+            .unwrap_or(Duration::from_secs(self.ncsi_timeout))
+    }
+
+    /// `--connect-mode`; `--strict-autoconnect` is shorthand for auto
+    fn connect_mode(&self) -> wlan::ConnectMode {
+        if self.strict_autoconnect {
+            wlan::ConnectMode::Auto
+        } else {
+            self.connect_mode
+        }
+    }
+
     fn connect_strategy(&self) -> ConnectStrategy {
         if let Some(ref names) = self.profiles {
             if !names.is_empty() {
@@ -57,88 +692,980 @@ impl Cli {
         }
         ConnectStrategy::ScanOnly
     }
+
+    fn proxy_mode(&self) -> network::ProxyMode {
+        if self.no_proxy {
+            return network::ProxyMode::Direct;
+        }
+        match self.proxy {
+            Some(ref url) => network::ProxyMode::Url(
+                url.clone(),
+                self.proxy_user
+                    .clone()
+                    .zip(self.proxy_pass.clone())
+                    .map(|(user, pass)| network::ProxyCredentials { user, pass }),
+            ),
+            None => network::ProxyMode::System,
+        }
+    }
+
+    fn recovery_options(&self) -> RecoveryOptions {
+        RecoveryOptions {
+            strategy: self.connect_strategy(),
+            roam_to_best: self.roam_to_best,
+            temporary: self.temporary,
+            scan_min_interval: Duration::from_secs(self.scan_min_interval),
+            scan_passes: self.scan_passes,
+            fail_fast_on_disconnect: self.fail_fast_on_disconnect,
+            band_only: self.band_only,
+            scan_memory: Duration::from_secs(self.scan_memory),
+            reconnect_current_first: self.reconnect_current_first,
+            connect_mode: self.connect_mode(),
+            suspend_autoconnect: self.suspend_autoconnect,
+            accept_associating: self.accept_associating,
+            profile_order: self.profile_order,
+            last_good: None,
+            require_wifi_route: self.require_wifi_route,
+            concurrent_interfaces: self.concurrent_interfaces,
+            shutdown: CancellationToken::new(),
+            adapter_wait: Duration::from_secs(self.adapter_wait),
+            connect_min_gap: Duration::from_millis(self.connect_min_gap_ms),
+            escalate: self.escalate.clone(),
+            skip_admin_steps: self.best_effort_noadmin && !admin::is_admin(),
+            eap_credentials: self
+                .eap_user
+                .clone()
+                .zip(self.eap_pass.clone())
+                .map(|(user, pass)| eap::EapCredentials { user, pass }),
+            connect_flags: self
+                .connect_flags
+                .iter()
+                .fold(HashMap::new(), |mut map, f| {
+                    *map.entry(f.profile.clone()).or_default() |= f.flags;
+                    map
+                }),
+        }
+    }
+}
+
+/// How often the loop logs the per-profile statistics table
+const STATS_LOG_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Consecutive weak-signal checks before --roam-floor looks for a stronger network
+const ROAM_FLOOR_CHECKS: u32 = 3;
+
+/// One recovery round: radio on, then cycle saved profiles until the probe passes
+async fn recovery_round(
+    prober: Arc<network::Prober>,
+    opts: RecoveryOptions,
+    ethernet_aware: bool,
+) -> (anyhow::Result<wlan::ConnectedNetwork>, wlan::RecoveryReport) {
+    let mut report = wlan::RecoveryReport::default();
+    let wifi = wifi_recovery(Arc::clone(&prober), &opts, &mut report);
+    let result = if ethernet_aware {
+        tokio::select! {
+            result = wifi => result,
+            conn = wait_for_ethernet(prober) => Ok(conn),
+        }
+    } else {
+        wifi.await
+    };
+    (result, report)
+}
+
+async fn wifi_recovery(
+    prober: Arc<network::Prober>,
+    opts: &RecoveryOptions,
+    report: &mut wlan::RecoveryReport,
+) -> anyhow::Result<wlan::ConnectedNetwork> {
+    if !opts.escalate.is_empty() {
+        if let Some(conn) = escalate::climb(&opts.escalate, &prober, opts, report).await {
+            return Ok(conn);
+        }
+        tracing::info!("Escalation ladder exhausted, running the regular recovery round");
+    }
+    tracing::info!("Step 1/2: Turn on Wi-Fi radio");
+    if let Some(conn) = wlan::current_connection() {
+        tracing::info!(
+            "Wi-Fi already associated with \"{}\", radio is on; skipping radio step \
+             (outage is likely upstream)",
+            conn.profile
+        );
+    } else if opts.skip_admin_steps {
+        tracing::info!("Skipping radio step: needs administrator rights (--best-effort-noadmin)");
+    } else if let Err(e) = radio::turn_on_wifi_radio().await {
+        if e.downcast_ref::<radio::RadioApiUnavailable>().is_some() {
+            // Warned once when first detected
+            tracing::debug!("Skipping radio step: {}", e);
+        } else {
+            tracing::warn!(
+                "Failed to turn on Wi-Fi radio: {} (continuing with saved profiles)",
+                e
+            );
+        }
+    } else {
+        tracing::info!("Wi-Fi radio ready");
+    }
+
+    tracing::info!("Step 2/2: Enumerate and connect saved Wi-Fi profiles (filtered by strategy)");
+    wlan::connect_any_saved_wifi(
+        move || {
+            let p = Arc::clone(&prober);
+            Box::pin(async move { p.probe().await })
+        },
+        opts,
+        report,
+    )
+    .await
+}
+
+/// How often `--ethernet-aware` re-checks Ethernet during a recovery round
+const ETHERNET_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Resolve once an Ethernet adapter is up and the probe passes (`--ethernet-aware`).
+/// The returned connection names the adapter as its profile and has no SSID.
+async fn wait_for_ethernet(prober: Arc<network::Prober>) -> wlan::ConnectedNetwork {
+    loop {
+        sleep(ETHERNET_CHECK_INTERVAL).await;
+        let Some((name, ip)) = ipconfig::ethernet_up() else {
+            continue;
+        };
+        if prober.probe().await {
+            tracing::info!(
+                "Ethernet \"{}\" is up with connectivity, abandoning Wi-Fi recovery",
+                name
+            );
+            return wlan::ConnectedNetwork {
+                profile: name,
+                ssid: String::new(),
+                bssid: [0; 6],
+                signal_quality: 0,
+                ip: Some(ip),
+            };
+        }
+    }
+}
+
+/// Exit code: network still down (recovery attempted but failed, or skipped)
+const EXIT_NETWORK_DOWN: i32 = 2;
+
+/// Exit code: no WLAN hardware, recovery impossible
+const EXIT_NO_HARDWARE: i32 = 3;
+
+/// Exit code: not admin and elevation declined or failed
+const EXIT_NOT_ADMIN: i32 = 4;
+
+/// Exit code: the elevated relaunch started but is still not admin
+const EXIT_ELEVATION_DENIED: i32 = 5;
+
+/// Exit code: stopped by Ctrl+C or the tray's Quit, whatever the network state (the code shells
+/// report for a program interrupted by Ctrl+C)
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// Finish with the given exit code (0 returns normally from main)
+fn exit_with(code: i32) -> anyhow::Result<()> {
+    if code != 0 {
+        tray::remove_icon();
+        std::process::exit(code);
+    }
+    Ok(())
+}
+
+/// Set by the tray's Pause: the loop skips checks and recovery until resumed
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+/// Wakes the loop's interval sleep early (tray "Check now", --check-hotkey)
+static CHECK_NOW: Notify = Notify::const_new();
+
+/// Act on tray menu picks; Quit cancels `shutdown` like Ctrl+C
+fn spawn_tray_commands(
+    mut commands: tokio::sync::mpsc::UnboundedReceiver<tray::TrayCommand>,
+    shutdown: CancellationToken,
+) {
+    tokio::spawn(async move {
+        while let Some(command) = commands.recv().await {
+            match command {
+                tray::TrayCommand::CheckNow => {
+                    tracing::info!("Check requested from the tray");
+                    CHECK_NOW.notify_one();
+                }
+                tray::TrayCommand::Pause(paused) => {
+                    tracing::info!(
+                        "{} from the tray",
+                        if paused { "Paused" } else { "Resumed" }
+                    );
+                    PAUSED.store(paused, Ordering::Relaxed);
+                    CHECK_NOW.notify_one();
+                }
+                tray::TrayCommand::Quit => {
+                    tracing::info!("Quit requested from the tray");
+                    shutdown.cancel();
+                }
+            }
+        }
+    });
+}
+
+/// Sleep for `wait` (see `timing::next_sleep`), cut short by the --max-runtime deadline or
+/// Ctrl+C, or ended early by a check request. Returns false once the deadline has been reached
+/// or shutdown was requested.
+async fn sleep_within_deadline(
+    wait: Duration,
+    deadline: Option<Instant>,
+    shutdown: &CancellationToken,
+) -> bool {
+    let wake = Instant::now() + wait;
+    let (duration, more) = match deadline {
+        Some(d) if d <= wake => (d.saturating_duration_since(Instant::now()), false),
+        _ => (wait, true),
+    };
+    tokio::select! {
+        _ = sleep(duration) => more,
+        _ = CHECK_NOW.notified() => true,
+        _ = shutdown.cancelled() => false,
+    }
+}
+
+/// Leave the loop once `sleep_within_deadline` returned false: on a shutdown request or at
+/// the --max-runtime deadline
+fn leave_loop(down: bool, shutdown: &CancellationToken) -> anyhow::Result<()> {
+    if shutdown.is_cancelled() {
+        exit_on_shutdown()
+    } else {
+        exit_at_deadline(down)
+    }
+}
+
+/// Leave the loop on Ctrl+C or tray Quit. The exit code never reflects the network state, so
+/// scripts cannot mistake an interrupt for an outage.
+fn exit_on_shutdown() -> anyhow::Result<()> {
+    tracing::info!("Shutdown requested, exiting");
+    exit_with(EXIT_INTERRUPTED)
+}
+
+/// Leave the loop at the --max-runtime deadline, with an exit code reflecting the last state
+fn exit_at_deadline(down: bool) -> anyhow::Result<()> {
+    if down {
+        tracing::warn!("--max-runtime reached, network still down, exiting");
+        return exit_with(EXIT_NETWORK_DOWN);
+    }
+    tracing::info!("--max-runtime reached, network up, exiting");
+    Ok(())
+}
+
+/// First Ctrl+C cancels `shutdown` (the loop and any recovery in progress wind down and exit);
+/// a second one exits immediately, for when a WLAN call is hung
+fn spawn_ctrl_c_handler(shutdown: CancellationToken) {
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        tracing::info!("Ctrl+C received, shutting down (press again to exit immediately)");
+        shutdown.cancel();
+        if tokio::signal::ctrl_c().await.is_ok() {
+            tray::remove_icon();
+            std::process::exit(EXIT_INTERRUPTED);
+        }
+    });
+}
+
+/// Open `--log-file` for appending, creating its directory
+fn open_log_file(path: &Path) -> anyhow::Result<std::fs::File> {
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| anyhow::anyhow!("Failed to open log file {}: {}", path.display(), e))
+}
+
+/// How long a panic waits for `--log-sink` to send the queued lines before the process dies
+const PANIC_DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Log panics through tracing (so they reach `--log-sink` with the active span, e.g. the profile
+/// being connected) and give the sink a moment to send them, then run the default hook
+fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let payload = info
+            .payload()
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| info.payload().downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("<non-string payload>");
+        let location = info
+            .location()
+            .map(|l| format!("{}:{}", l.file(), l.line()))
+            .unwrap_or_else(|| "<unknown>".to_string());
+        let thread = std::thread::current();
+        tracing::error!(
+            "Panic on thread {} at {}: {}",
+            thread.name().unwrap_or("<unnamed>"),
+            location,
+            payload
+        );
+        logsink::drain(PANIC_DRAIN_TIMEOUT);
+        default_hook(info);
+    }));
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let cli = Cli::parse();
+    // clap exits with 2 on usage errors, which would collide with EXIT_NETWORK_DOWN
+    let (cli, warnings) = Cli::command()
+        .try_get_matches()
+        .and_then(|matches| {
+            let cli = Cli::from_arg_matches(&matches)?;
+            let warnings = cli.validate(&matches)?;
+            Ok((cli, warnings))
+        })
+        .unwrap_or_else(|e| {
+            let _ = e.print();
+            std::process::exit(if e.use_stderr() { 1 } else { 0 });
+        });
 
-    tracing_subscriber::fmt()
-        .with_env_filter(
+    if cli.version {
+        version::print();
+        return Ok(());
+    }
+
+    let log_file = cli.log_file.as_deref().map(open_log_file).transpose()?;
+    tracing_subscriber::registry()
+        .with(
             tracing_subscriber::EnvFilter::from_default_env()
                 .add_directive(tracing::Level::INFO.into()),
         )
+        .with(tracing_subscriber::fmt::layer())
+        .with(cli.log_sink.clone().map(|target| {
+            tracing_subscriber::fmt::layer()
+                .json()
+                .with_writer(logsink::LogSink::spawn(target))
+        }))
+        .with(log_file.map(|file| {
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(std::sync::Mutex::new(file))
+        }))
         .init();
+    install_panic_hook();
+    for warning in &warnings {
+        tracing::warn!("{}", warning);
+    }
+
+    if cli.print_schema {
+        return types::print_schema();
+    }
+    let state_path = cli.state_file.clone().unwrap_or_else(state::default_path);
+    if cli.status {
+        return state::print_status(&state_path, cli.json);
+    }
+    if cli.list_bss {
+        println!("{}", serde_json::to_string_pretty(&wlan::bss_report()?)?);
+        return Ok(());
+    }
 
-    admin::ensure_admin_or_elevate()?;
-    let strategy = cli.connect_strategy();
+    if cli.best_effort_noadmin && !admin::is_admin() {
+        tracing::warn!(
+            "Not running as administrator (--best-effort-noadmin): continuing without elevation. \
+             Probing, hooks and WLAN scan/connect usually still work; turning on the Wi-Fi radio \
+             and enabling a disabled adapter will be skipped"
+        );
+    } else if let Err(e) = admin::ensure_admin_or_elevate(cli.elevated_child) {
+        tracing::error!("{}", e);
+        if e.downcast_ref::<admin::ElevationDenied>().is_some() {
+            std::process::exit(EXIT_ELEVATION_DENIED);
+        }
+        std::process::exit(EXIT_NOT_ADMIN);
+    }
+    if cli.install_task {
+        return task::install(cli.task_trigger);
+    }
+    if cli.uninstall_task {
+        return task::uninstall();
+    }
+    if cli.detach {
+        let default_log = detach::default_log_path();
+        let extra_log = cli.log_file.is_none().then_some(default_log.as_path());
+        let pid = detach::spawn_detached(extra_log)?;
+        tracing::info!(
+            "Running in the background (PID {}), logging to {}; use --status to check on it",
+            pid,
+            cli.log_file.as_deref().unwrap_or(&default_log).display()
+        );
+        return Ok(());
+    }
+    if let Some(ref name) = cli.print_profile_xml {
+        println!("{}", wlan::saved_profile_xml(name, cli.show_key)?);
+        return Ok(());
+    }
+
+    let mut opts = cli.recovery_options();
+    spawn_ctrl_c_handler(opts.shutdown.clone());
 
     tracing::info!(
         "Network Watchdog started, strategy: {:?}, mode: {}",
-        strategy,
+        opts.strategy,
         if cli.once { "single run" } else { "loop" }
     );
     if !cli.once {
         tracing::info!("Checking network every {} s", cli.interval);
     }
+    let policy_blocks = policy::wifi_policy_blocks();
+    if !policy_blocks.is_empty() {
+        for reason in &policy_blocks {
+            tracing::warn!("Policy: {}", reason);
+        }
+        tracing::warn!("Wi-Fi blocked by policy; recovery will not work");
+        if cli.fail_on_policy_block {
+            anyhow::bail!("Wi-Fi blocked by policy (--fail-on-policy-block)");
+        }
+    }
+
+    let deadline = cli.max_runtime.map(|secs| {
+        tracing::info!("Exiting after {} s (--max-runtime)", secs);
+        Instant::now() + Duration::from_secs(secs)
+    });
 
-    let ncsi_url: Arc<str> = Arc::from(cli.ncsi_url.as_str());
-    let ncsi_timeout = cli.ncsi_timeout;
+    let ncsi_urls = if cli.use_windows_ncsi {
+        match network::windows_ncsi_url() {
+            Some(url) => {
+                tracing::info!("Using Windows NCSI probe URL: {}", url);
+                vec![url]
+            }
+            None => {
+                tracing::warn!(
+                    "Windows NCSI probe settings not found, using {}",
+                    network::DEFAULT_NCSI_URL
+                );
+                vec![network::DEFAULT_NCSI_URL.to_string()]
+            }
+        }
+    } else {
+        cli.ncsi_url.clone()
+    };
+    let probe_timeout = if cli.cap_probe_to_interval && !cli.once {
+        cli.ncsi_timeout().min(Duration::from_secs(cli.interval))
+    } else {
+        cli.ncsi_timeout()
+    };
+    let prober = Arc::new(
+        network::Prober::new(&ncsi_urls, probe_timeout, cli.probe_cache_ms)
+            .with_probe_dns(cli.probe_dns)
+            .with_proxy(cli.proxy_mode())
+            .with_method(cli.probe_method)
+            .with_expected_header(cli.probe_expect_header.clone())
+            .with_style(cli.probe_style)
+            .with_health(cli.health, Duration::from_millis(cli.health_max_latency_ms))
+            .with_dns_diagnosis(cli.diagnose_dns)
+            .with_wifi_source(cli.probe_wifi_source)
+            .with_wifi_bind(cli.probe_bind_wifi)
+            .with_nlm(cli.use_nlm)
+            .with_protocols(&cli.probe_protocol, cli.probe_combine)
+            .with_retries(cli.probe_retries, cli.probe_retry_delay_ms)
+            .with_simulated_down(cli.simulate_down)
+            .with_concurrency(cli.probe_concurrency.map(|n| n as usize)),
+    );
+    if cli.survey {
+        let p = Arc::clone(&prober);
+        let entries = wlan::survey(
+            move || {
+                let p = Arc::clone(&p);
+                Box::pin(async move { p.probe().await })
+            },
+            &opts,
+        )
+        .await?;
+        wlan::print_survey_report(&entries);
+        return Ok(());
+    }
     let check_interval = cli.interval;
+    let timing = cli.timing();
+    let mut failure_streak = timing::FailureStreak::default();
+    let mut was_down = false;
+    let mut stats = cli
+        .stats_file
+        .as_deref()
+        .map(stats::Stats::load)
+        .unwrap_or_default();
+    let mut stats_logged_at = Instant::now();
+    let tray = if cli.tray {
+        match tray::Tray::spawn() {
+            Ok((tray, commands)) => {
+                spawn_tray_commands(commands, opts.shutdown.clone());
+                Some(tray)
+            }
+            Err(e) => {
+                tracing::warn!("Tray icon unavailable: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    if let Some(ref keys) = cli.check_hotkey {
+        match hotkey::spawn(keys.clone(), || {
+            tracing::info!("Check requested by hotkey");
+            CHECK_NOW.notify_one();
+        }) {
+            Ok(()) => tracing::info!("Press {} to check now", keys),
+            Err(e) => tracing::warn!("{}", e),
+        }
+    }
+    let mut state_file = state::StateFile::new(state_path, check_interval).with_tray(tray);
+    let mut weak_checks = 0u32;
+    let csv_log = cli
+        .csv_log
+        .clone()
+        .map(|path| csvlog::CsvLog::new(path, cli.csv_rotate));
+    let mut window = network::ProbeWindow::new(
+        cli.probe_window as usize,
+        cli.down_threshold as usize,
+        cli.up_threshold as usize,
+    );
+
+    let heartbeat = || {
+        if let Some(ref path) = cli.heartbeat_file {
+            state::touch_heartbeat(path);
+        }
+    };
 
     loop {
+        heartbeat();
+        if PAUSED.load(Ordering::Relaxed) {
+            tracing::info!("Paused from the tray, skipping this check");
+            let wait = timing.sleep_for(&LoopState::Paused);
+            if !sleep_within_deadline(wait, deadline, &opts.shutdown).await {
+                return leave_loop(was_down, &opts.shutdown);
+            }
+            continue;
+        }
+        if let Some(ref pinned) = cli.pin_profile {
+            if let Err(e) = wlan::enforce_pinned_profile(pinned, &opts).await {
+                tracing::warn!("Pin enforcement failed: {}", e);
+            }
+        }
+
         tracing::info!("Checking network...");
-        if network::test_network(&ncsi_url, ncsi_timeout).await {
-            tracing::info!("Network OK");
+        let probe_started = Instant::now();
+        let probe_ok = if cli.cap_probe_to_interval && !cli.once {
+            let cap = Duration::from_secs(check_interval);
+            tokio::time::timeout(cap, prober.probe())
+                .await
+                .unwrap_or_else(|_| {
+                    tracing::warn!(
+                        "Probe still running after {} s (--cap-probe-to-interval), counting it \
+                         as failed",
+                        check_interval
+                    );
+                    false
+                })
+        } else {
+            prober.probe().await
+        };
+        // Latency of the probe that answered: the backup's own round-trip when it rescues a
+        // failed primary, never the primary's time to fail
+        let mut latency = probe_ok.then(|| probe_started.elapsed());
+        let probe_ok = match cli.backup_ncsi_url {
+            Some(ref backup) if !probe_ok && !prober.simulated() => {
+                let backup_started = Instant::now();
+                if prober.probe_url(backup).await {
+                    latency = Some(backup_started.elapsed());
+                    tracing::warn!(
+                        "NCSI endpoint(s) {} failed but backup {} answered: the NCSI host is \
+                         down, not the network; treating it as up",
+                        ncsi_urls.join(", "),
+                        backup
+                    );
+                    true
+                } else {
+                    tracing::info!(
+                        "NCSI endpoint(s) {} and backup {} all failed",
+                        ncsi_urls.join(", "),
+                        backup
+                    );
+                    false
+                }
+            }
+            _ => probe_ok,
+        };
+        if let Some(ref csv) = csv_log {
+            let conn = wlan::current_connection();
+            csv.record(probe_ok, latency, conn.as_ref());
+        }
+        let verdict = window.record(probe_ok);
+        state_file.set_rtt(prober.rtt_stats());
+        state_file.set_probe_error(if probe_ok {
+            None
+        } else {
+            prober.last_failure()
+        });
+        if !probe_ok && verdict {
+            tracing::info!(
+                "Probe failed, still treating the network as up ({}; down at {})",
+                window.summary(),
+                window.down_threshold()
+            );
+        }
+        if probe_ok && !verdict {
+            // Down and not yet trusted again: no recovery while probes pass
+            tracing::info!(
+                "Probe passed, still treating the network as down ({}; up at {})",
+                window.summary(),
+                window.up_threshold()
+            );
+            state_file.update(state::NetState::Down, None);
             if cli.once {
                 tracing::info!("--once mode, exiting");
-                return Ok(());
+                return exit_with(EXIT_NETWORK_DOWN);
+            }
+            let wait = timing.sleep_for(&LoopState::Down);
+            tracing::info!("Sleeping {} s...", wait.as_secs());
+            if !sleep_within_deadline(wait, deadline, &opts.shutdown).await {
+                return leave_loop(was_down, &opts.shutdown);
+            }
+            continue;
+        }
+        if verdict {
+            let internal_down = match cli.internal_url {
+                Some(ref url) if !prober.probe_url(url).await => {
+                    tracing::warn!(
+                        "Internet reachable but internal endpoint {} is not (VPN down?); \
+                         Wi-Fi recovery cannot fix this",
+                        url
+                    );
+                    cli.require_internal
+                }
+                _ => false,
+            };
+            if internal_down {
+                state_file.update(state::NetState::Down, None);
+                if !was_down {
+                    was_down = true;
+                    if let Some(ref cmd) = cli.on_down {
+                        hooks::run_hook("down", cmd, None, None, cli.hook_timeout).await;
+                    }
+                }
+            } else {
+                tracing::info!("Network OK");
+                failure_streak.reset();
+                let current = wlan::current_connection();
+                state_file.update(state::NetState::Up, current.as_ref());
+                if let Some(floor) = cli.roam_floor {
+                    weak_checks = match current {
+                        Some(ref c) if c.signal_quality < floor => {
+                            tracing::info!(
+                                "Signal {}% below --roam-floor {}% ({}/{})",
+                                c.signal_quality,
+                                floor,
+                                weak_checks + 1,
+                                ROAM_FLOOR_CHECKS
+                            );
+                            weak_checks + 1
+                        }
+                        _ => 0,
+                    };
+                    if weak_checks >= ROAM_FLOOR_CHECKS {
+                        weak_checks = 0;
+                        match wlan::roam_off_weak_link(floor, &opts).await {
+                            Ok(Some(conn)) => {
+                                state_file.update(state::NetState::Up, Some(&conn));
+                            }
+                            Ok(None) => {}
+                            Err(e) => tracing::warn!("Roam floor: {}", e),
+                        }
+                    }
+                }
+                if was_down {
+                    was_down = false;
+                    if let Some(ref cmd) = cli.on_restored {
+                        hooks::run_hook("restored", cmd, None, None, cli.hook_timeout).await;
+                    }
+                }
+            }
+            if cli.once {
+                tracing::info!("--once mode, exiting");
+                return exit_with(if was_down { EXIT_NETWORK_DOWN } else { 0 });
+            }
+            let wait = timing.sleep_for(&LoopState::Up);
+            tracing::info!("Sleeping {} s...", wait.as_secs());
+            if !sleep_within_deadline(wait, deadline, &opts.shutdown).await {
+                return leave_loop(was_down, &opts.shutdown);
             }
-            tracing::info!("Sleeping {} s...", check_interval);
-            sleep(Duration::from_secs(check_interval)).await;
             continue;
         }
 
-        tracing::warn!("Network unreachable, attempting Wi-Fi recovery");
-
-        tracing::info!("Step 1/2: Turn on Wi-Fi radio");
-        if let Err(e) = radio::turn_on_wifi_radio().await {
-            tracing::warn!(
-                "Failed to turn on Wi-Fi radio: {} (continuing with saved profiles)",
-                e
-            );
+        if cli.monitor_only {
+            tracing::warn!("Network unreachable (--monitor-only: no recovery)");
+            state_file.update(state::NetState::Down, None);
         } else {
-            tracing::info!("Wi-Fi radio ready");
+            tracing::warn!("Network unreachable, attempting Wi-Fi recovery");
+            state_file.update(state::NetState::Recovering, None);
+        }
+        if !was_down {
+            was_down = true;
+            if let Some(ref cmd) = cli.on_down {
+                hooks::run_hook("down", cmd, None, None, cli.hook_timeout).await;
+            }
         }
 
+        let suppressed = match cli.active_hours {
+            Some(hours) if !hours.contains_now() => {
+                Some(format!("Outside active hours ({})", hours))
+            }
+            _ if !cli.recover_on.allows(power::power_source()) => {
+                Some(format!("Not {} (--recover-on)", cli.recover_on))
+            }
+            _ if !cli.monitor_only && !cli.skip_recovery_if_ssid.is_empty() => {
+                wlan::current_connection()
+                    .filter(|c| cli.skip_recovery_if_ssid.contains(&c.ssid))
+                    .map(|c| format!("Connected to \"{}\" (--skip-recovery-if-ssid)", c.ssid))
+            }
+            _ => None,
+        };
+        if let Some(reason) = suppressed {
+            tracing::info!("{}, recovery suppressed", reason);
+            state_file.update(state::NetState::Down, None);
+            if cli.once {
+                tracing::info!("--once mode, exiting");
+                return exit_with(EXIT_NETWORK_DOWN);
+            }
+            let wait = timing.sleep_for(&LoopState::Down);
+            tracing::info!("Sleeping {} s...", wait.as_secs());
+            if !sleep_within_deadline(wait, deadline, &opts.shutdown).await {
+                return leave_loop(was_down, &opts.shutdown);
+            }
+            continue;
+        }
+
+        if cli.monitor_only {
+            if let Err(e) = wlan::log_visible_saved_networks(&opts).await {
+                tracing::warn!("Scan failed: {}", e);
+            }
+            if cli.once {
+                tracing::info!("--once mode, exiting");
+                return exit_with(EXIT_NETWORK_DOWN);
+            }
+            let wait = timing.sleep_for(&LoopState::Down);
+            tracing::info!("Sleeping {} s...", wait.as_secs());
+            if !sleep_within_deadline(wait, deadline, &opts.shutdown).await {
+                return leave_loop(was_down, &opts.shutdown);
+            }
+            continue;
+        }
+
+        heartbeat();
+        let round_start = Instant::now();
+        opts.last_good = stats.last_good.clone();
+        let before = if cli.restore_on_fail {
+            let snapshot = wlan::association_snapshot();
+            match snapshot {
+                Some(ref s) => tracing::info!(
+                    "Pre-recovery association: \"{}\" (SSID \"{}\")",
+                    s.profile,
+                    s.ssid
+                ),
+                None => tracing::info!("Pre-recovery association: not connected"),
+            }
+            snapshot
+        } else {
+            None
+        };
+        let round = tokio::spawn(recovery_round(
+            Arc::clone(&prober),
+            opts.clone(),
+            cli.ethernet_aware,
+        ));
+        let round_abort = round.abort_handle();
+        let (result, report) =
+            match tokio::time::timeout(Duration::from_secs(cli.round_hard_timeout), round).await {
+                Ok(Ok(outcome)) => outcome,
+                Ok(Err(e)) => (
+                    Err(anyhow::anyhow!("Recovery round aborted: {}", e)),
+                    wlan::RecoveryReport::default(),
+                ),
+                Err(_) => {
+                    tracing::error!(
+                        "Recovery round still running after {} s (--round-hard-timeout), \
+                         aborting it; a WLAN/WinRT call is probably hung",
+                        cli.round_hard_timeout
+                    );
+                    // Dropping the JoinHandle would only detach the task: a slow (not hung)
+                    // round would keep connecting into the next one
+                    round_abort.abort();
+                    (
+                        Err(anyhow::anyhow!("Recovery round hit the hard timeout")),
+                        wlan::RecoveryReport::default(),
+                    )
+                }
+            };
+
+        heartbeat();
         tracing::info!(
-            "Step 2/2: Enumerate and connect saved Wi-Fi profiles (filtered by strategy)"
+            "Round summary: tried {} profile(s), winner: {}, took {:.1} s, final state: {}{}",
+            report.attempts.len(),
+            result
+                .as_ref()
+                .map(|c| format!("\"{}\"", c.profile))
+                .unwrap_or_else(|_| "none".to_string()),
+            round_start.elapsed().as_secs_f64(),
+            if result.is_ok() { "UP" } else { "DOWN" },
+            if report.interfaces > 1 {
+                format!(", interfaces: {}", report.interfaces)
+            } else {
+                String::new()
+            }
         );
-        let url = Arc::clone(&ncsi_url);
-        let timeout = ncsi_timeout;
-        let result = wlan::connect_any_saved_wifi(
-            move || {
-                let u = Arc::clone(&url);
-                Box::pin(async move { network::test_network(&u, timeout).await })
-            },
-            strategy.clone(),
-        )
-        .await;
 
+        state_file.set_last_recovery(types::RecoveryEvent::from_round(
+            &result,
+            &report,
+            round_start.elapsed(),
+        ));
+        let no_hardware = result
+            .as_ref()
+            .is_err_and(|e| e.downcast_ref::<wlan::NoWifiHardware>().is_some());
+        let (round_exit_code, round_state) = match result {
+            Ok(_) => {
+                failure_streak.reset();
+                (0, LoopState::Recovered)
+            }
+            Err(_) if no_hardware => (EXIT_NO_HARDWARE, LoopState::NoHardware),
+            Err(_) => (EXIT_NETWORK_DOWN, failure_streak.failed()),
+        };
         match result {
-            Ok(()) => {
+            Ok(connected) => {
                 tracing::info!("Network restored");
+                state_file.update(state::NetState::Up, Some(&connected));
+                was_down = false;
+                window.reset();
+                if let Some(ref cmd) = cli.on_restored {
+                    hooks::run_hook(
+                        "restored",
+                        cmd,
+                        Some(&connected.ssid),
+                        Some(&connected.profile),
+                        cli.hook_timeout,
+                    )
+                    .await;
+                }
             }
             Err(e) => {
                 tracing::warn!("Recovery failed this round: {}", e);
+                if let Some(ref snapshot) = before {
+                    if !opts.shutdown.is_cancelled() {
+                        if let Err(e) = wlan::restore_association(snapshot, &opts).await {
+                            tracing::warn!("--restore-on-fail: {}", e);
+                        }
+                    }
+                }
+                state_file.update(state::NetState::Down, None);
+                if cli.beep_on_fail {
+                    alert::beep_failure();
+                }
             }
         }
 
+        stats.record(&report);
+        if let Some(ref path) = cli.stats_file {
+            stats.save(path);
+        }
+        if opts.shutdown.is_cancelled() {
+            return exit_on_shutdown();
+        }
+
         if cli.once {
+            stats.log_table();
             tracing::info!("--once mode, exiting after one run");
-            return Ok(());
+            return exit_with(round_exit_code);
+        }
+        if stats_logged_at.elapsed() >= STATS_LOG_INTERVAL {
+            stats.log_table();
+            stats_logged_at = Instant::now();
+        }
+        let wait = timing.sleep_for(&round_state);
+        tracing::info!("Sleeping {} s...", wait.as_secs());
+        if !sleep_within_deadline(wait, deadline, &opts.shutdown).await {
+            return leave_loop(was_down, &opts.shutdown);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse `args` like `main` does, through clap and then `Cli::validate`
+    fn parse(args: &[&str]) -> Result<Vec<String>, clap::Error> {
+        let matches = Cli::command().try_get_matches_from(
+            std::iter::once("network-watchdog").chain(args.iter().copied()),
+        )?;
+        let cli = Cli::from_arg_matches(&matches)?;
+        cli.validate(&matches)
+    }
+
+    fn error_kind(args: &[&str]) -> ErrorKind {
+        parse(args)
+            .expect_err("combination should be rejected")
+            .kind()
+    }
+
+    #[test]
+    fn rejects_all_with_profiles() {
+        assert_eq!(
+            error_kind(&["--all", "--profiles", "Home"]),
+            ErrorKind::ArgumentConflict
+        );
+    }
+
+    #[test]
+    fn rejects_zero_interval() {
+        assert_eq!(error_kind(&["--interval", "0"]), ErrorKind::ValueValidation);
+    }
+
+    #[test]
+    fn rejects_once_with_max_runtime() {
+        assert_eq!(
+            error_kind(&["--once", "--max-runtime", "5"]),
+            ErrorKind::ArgumentConflict
+        );
+    }
+
+    #[test]
+    fn rejects_monitor_only_with_simulate_down() {
+        assert_eq!(
+            error_kind(&["--monitor-only", "--simulate-down", "1"]),
+            ErrorKind::ArgumentConflict
+        );
+    }
+
+    #[test]
+    fn both_auto_mode_spellings_reject_the_same_flags() {
+        for flag in [
+            &["--all"][..],
+            &["--profiles", "Home"],
+            &["--temporary"],
+            &["--roam-to-best"],
+            &["--concurrent-interfaces"],
+            &["--suspend-autoconnect"],
+        ] {
+            for auto in [&["--strict-autoconnect"][..], &["--connect-mode", "auto"]] {
+                let args: Vec<&str> = auto.iter().chain(flag).copied().collect();
+                assert_eq!(error_kind(&args), ErrorKind::ArgumentConflict, "{:?}", args);
+            }
         }
-        tracing::info!("Sleeping {} s...", check_interval);
-        sleep(Duration::from_secs(check_interval)).await;
+    }
+
+    #[test]
+    fn warns_about_interval_with_once() {
+        let warnings = parse(&["--once", "--interval", "30"]).unwrap();
+        assert_eq!(warnings, ["--interval has no effect with --once"]);
+    }
+
+    #[test]
+    fn accepts_defaults_without_warnings() {
+        assert!(parse(&[]).unwrap().is_empty());
     }
 }
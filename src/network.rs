@@ -1,26 +1,1264 @@
 //! NCSI network probe
 
+use std::collections::VecDeque;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use hickory_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
+use hickory_resolver::TokioAsyncResolver;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
+
+use crate::probes::{self, ProbeCombine, ProbeProtocol};
+use crate::{nlm, registry, route, wlan};
+
 /// Default NCSI URL (Windows NCSI endpoint)
 pub const DEFAULT_NCSI_URL: &str = "http://www.msftconnecttest.com/connecttest.txt";
 
+/// Registry key holding the active probe settings Windows NCSI itself uses
+const WINDOWS_NCSI_KEY: &str = r"SYSTEM\CurrentControlSet\Services\NlaSvc\Parameters\Internet";
+
+/// Probe URL Windows NCSI uses (ActiveWebProbeHost + ActiveWebProbePath); None if not set
+pub fn windows_ncsi_url() -> Option<String> {
+    let host = registry::read_hklm_string(WINDOWS_NCSI_KEY, "ActiveWebProbeHost")?;
+    let path = registry::read_hklm_string(WINDOWS_NCSI_KEY, "ActiveWebProbePath")?;
+    if host.is_empty() {
+        return None;
+    }
+    Some(format!("http://{}/{}", host, path.trim_start_matches('/')))
+}
+
 /// Default NCSI request timeout in seconds
 pub const DEFAULT_NCSI_TIMEOUT_SECS: u64 = 5;
 
-/// Probe network reachability using the given NCSI endpoint
-pub async fn test_network(url: &str, timeout_secs: u64) -> bool {
-    tracing::debug!("Requesting NCSI: {} (timeout {} s)", url, timeout_secs);
-    let client = reqwest::Client::new();
-    let result = client
-        .get(url)
-        .timeout(std::time::Duration::from_secs(timeout_secs))
-        .send()
-        .await
-        .map(|r| r.status().is_success())
-        .unwrap_or(false);
-    if result {
-        tracing::debug!("NCSI probe: OK");
-    } else {
-        tracing::debug!("NCSI probe: failed or timeout");
+/// DNS-free reachability target for `--diagnose-dns` (Cloudflare; any HTTP answer counts)
+const RAW_IP_PROBE_URL: &str = "http://1.1.1.1/";
+
+/// Whether `url` answers at all (any HTTP status, redirects not followed)
+async fn reachable(url: &str, timeout: Duration, proxy: &ProxyMode) -> bool {
+    let Ok(builder) = client_builder(proxy) else {
+        return false;
+    };
+    let Ok(client) = builder.redirect(reqwest::redirect::Policy::none()).build() else {
+        return false;
+    };
+    client.head(url).timeout(timeout).send().await.is_ok()
+}
+
+/// Proxy used for probe requests
+#[derive(Clone, Debug, Default)]
+pub enum ProxyMode {
+    /// System proxy settings (reqwest default)
+    #[default]
+    System,
+    /// No proxy: probe reflects direct connectivity
+    Direct,
+    /// Always use this proxy URL, with Basic credentials if given
+    Url(String, Option<ProxyCredentials>),
+}
+
+/// Basic auth for `--proxy` (`--proxy-user` / `--proxy-pass`)
+#[derive(Clone)]
+pub struct ProxyCredentials {
+    pub user: String,
+    pub pass: String,
+}
+
+impl fmt::Debug for ProxyCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProxyCredentials")
+            .field("user", &self.user)
+            .field("pass", &"***")
+            .finish()
+    }
+}
+
+/// HTTP method used for probe requests
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ProbeMethod {
+    #[default]
+    Get,
+    /// Status code only, no body transferred (for large custom probe URLs on metered links)
+    Head,
+    /// No request: ask Windows for its own connectivity level (GET when that fails)
+    Wininet,
+}
+
+/// What a successful probe response looks like (`--probe-style`)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ProbeStyle {
+    /// gen204 for URLs ending in generate_204 or gen_204, custom otherwise
+    #[default]
+    Auto,
+    /// Windows NCSI: 200 with the "Microsoft Connect Test" / "Microsoft NCSI" body
+    Ncsi,
+    /// Android-style generate_204: exactly 204 with an empty body
+    Gen204,
+    /// Any 2xx status
+    Custom,
+}
+
+/// Body Windows NCSI endpoints return (connecttest.txt, ncsi.txt)
+const NCSI_SENTINELS: &[&str] = &["Microsoft Connect Test", "Microsoft NCSI"];
+
+impl ProbeStyle {
+    /// Resolve `Auto` for this URL
+    fn for_url(self, url: &str) -> Self {
+        if self != ProbeStyle::Auto {
+            return self;
+        }
+        let path = reqwest::Url::parse(url)
+            .map(|u| u.path().to_string())
+            .unwrap_or_default();
+        if path.ends_with("/generate_204") || path.ends_with("/gen_204") {
+            ProbeStyle::Gen204
+        } else {
+            ProbeStyle::Custom
+        }
+    }
+
+    /// Whether the status fits the style; the body is checked separately
+    fn status_ok(self, status: reqwest::StatusCode) -> bool {
+        match self {
+            ProbeStyle::Ncsi => status == reqwest::StatusCode::OK,
+            ProbeStyle::Gen204 => status == reqwest::StatusCode::NO_CONTENT,
+            ProbeStyle::Auto | ProbeStyle::Custom => status.is_success(),
+        }
+    }
+
+    /// Whether a GET body fits the style: a captive portal answers with its own page
+    fn body_ok(self, body: &str) -> bool {
+        let ok = match self {
+            ProbeStyle::Ncsi => NCSI_SENTINELS.iter().any(|s| body.trim() == *s),
+            ProbeStyle::Gen204 => body.is_empty(),
+            ProbeStyle::Auto | ProbeStyle::Custom => true,
+        };
+        if !ok {
+            tracing::debug!(
+                "Probe body does not match the {:?} style (captive portal?)",
+                self
+            );
+        }
+        ok
+    }
+}
+
+/// How strict a probe is about calling the network healthy (`--health`)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum HealthLevel {
+    /// The response only has to fit `--probe-style` (and `--probe-expect-header`, if set)
+    #[default]
+    Lenient,
+    /// GET with redirects not followed, sentinel body (NCSI text, or the empty 204 of a
+    /// generate_204 URL) and a round-trip under `--health-max-latency-ms`
+    Strict,
+}
+
+/// Default `--health-max-latency-ms`
+pub const DEFAULT_HEALTH_MAX_LATENCY_MS: u64 = 1000;
+
+impl HealthLevel {
+    /// Probe style actually checked: strict always demands a sentinel body
+    fn style(self, style: ProbeStyle) -> ProbeStyle {
+        match (self, style) {
+            (HealthLevel::Lenient, style) | (HealthLevel::Strict, style @ ProbeStyle::Gen204) => {
+                style
+            }
+            (HealthLevel::Strict, _) => ProbeStyle::Ncsi,
+        }
+    }
+}
+
+/// Why an HTTP probe failed: logged at debug and kept in the state file
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ProbeFailure {
+    /// No answer within the probe timeout
+    Timeout,
+    /// The endpoint's host name did not resolve
+    Dns,
+    /// TCP connect failed (refused, unreachable)
+    Connect,
+    /// TLS handshake or certificate error
+    Tls,
+    /// Other request error (bad URL, redirect loop, client setup)
+    Request,
+    /// The response body could not be read
+    Decode,
+    /// Unexpected status for the probe style (3xx redirects included)
+    Status,
+    /// Body does not match the probe style's sentinel
+    Body,
+    /// `--probe-expect-header` missing or different
+    Header,
+    /// Answered, but slower than `--health-max-latency-ms`
+    Slow,
+}
+
+impl ProbeFailure {
+    /// Classify a reqwest error; DNS and TLS failures surface as connect errors, so the
+    /// source chain is searched for the probe resolver's `DnsFailure` or a `rustls::Error`
+    fn from_error(e: &reqwest::Error) -> Self {
+        if e.is_timeout() {
+            return ProbeFailure::Timeout;
+        }
+        if e.is_body() || e.is_decode() {
+            return ProbeFailure::Decode;
+        }
+        let mut dns = false;
+        let mut tls = false;
+        let mut source: Option<&(dyn std::error::Error + 'static)> = Some(e);
+        while let Some(err) = source {
+            dns |= err.is::<DnsFailure>();
+            tls |= err.is::<rustls::Error>();
+            // io::Error::source skips the error it wraps (tokio-rustls wraps rustls errors)
+            if let Some(inner) = err
+                .downcast_ref::<std::io::Error>()
+                .and_then(|io| io.get_ref())
+            {
+                tls |= inner.is::<rustls::Error>();
+            }
+            source = err.source();
+        }
+        if dns {
+            ProbeFailure::Dns
+        } else if tls {
+            ProbeFailure::Tls
+        } else if e.is_connect() {
+            ProbeFailure::Connect
+        } else {
+            ProbeFailure::Request
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ProbeFailure::Timeout => "timeout",
+            ProbeFailure::Dns => "dns",
+            ProbeFailure::Connect => "connect",
+            ProbeFailure::Tls => "tls",
+            ProbeFailure::Request => "request",
+            ProbeFailure::Decode => "decode",
+            ProbeFailure::Status => "status",
+            ProbeFailure::Body => "body",
+            ProbeFailure::Header => "header",
+            ProbeFailure::Slow => "slow",
+        }
+    }
+}
+
+impl std::fmt::Display for ProbeFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Outcome of one probe request: Ok, or why it failed
+pub type ProbeResult = Result<(), ProbeFailure>;
+
+/// Response requirements shared by every HTTP probe request
+#[derive(Clone, Debug, Default)]
+pub struct ResponseCheck {
+    pub method: ProbeMethod,
+    pub style: ProbeStyle,
+    pub expect_header: Option<ExpectedHeader>,
+    /// Strict health only: slower responses count as down
+    pub max_latency: Duration,
+}
+
+/// `--probe-expect-header "X-Health: ok"`: a response header the real probe server sends
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExpectedHeader {
+    pub name: String,
+    pub value: String,
+}
+
+impl FromStr for ExpectedHeader {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected \"<Name>: <value>\", got \"{}\"", s))?;
+        let name = name.trim();
+        if reqwest::header::HeaderName::from_bytes(name.as_bytes()).is_err() {
+            return Err(format!("invalid header name \"{}\"", name));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            value: value.trim().to_string(),
+        })
+    }
+}
+
+impl ExpectedHeader {
+    /// Whether the response carries the header with this value (name case-insensitive)
+    fn matches(&self, response: &reqwest::Response) -> bool {
+        let ok = response
+            .headers()
+            .get_all(self.name.as_str())
+            .iter()
+            .any(|v| v.to_str().is_ok_and(|v| v.trim() == self.value));
+        if !ok {
+            tracing::debug!(
+                "Probe response lacks \"{}: {}\" (proxy or captive portal answering?)",
+                self.name,
+                self.value
+            );
+        }
+        ok
+    }
+}
+
+/// Name resolution failure of a probe request, wrapped so `ProbeFailure::from_error` can
+/// recognise it by type
+#[derive(Debug)]
+struct DnsFailure(std::io::Error);
+
+impl std::fmt::Display for DnsFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "dns error: {}", self.0)
+    }
+}
+
+impl std::error::Error for DnsFailure {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+/// The system resolver (getaddrinfo, like reqwest's default) with its errors tagged as
+/// `DnsFailure`
+struct SystemResolver;
+
+impl reqwest::dns::Resolve for SystemResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            // The port is replaced by the URL's
+            let addrs = tokio::net::lookup_host((host, 0))
+                .await
+                .map_err(|e| Box::new(DnsFailure(e)) as Box<dyn std::error::Error + Send + Sync>)?;
+            Ok(Box::new(addrs) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+/// HTTP client builder with the proxy mode applied
+fn client_builder(proxy: &ProxyMode) -> anyhow::Result<reqwest::ClientBuilder> {
+    let builder = reqwest::Client::builder().dns_resolver(std::sync::Arc::new(SystemResolver));
+    Ok(match proxy {
+        ProxyMode::System => builder,
+        ProxyMode::Direct => builder.no_proxy(),
+        ProxyMode::Url(url, creds) => {
+            let proxy = reqwest::Proxy::all(url)?;
+            builder.proxy(match creds {
+                Some(c) => proxy.basic_auth(&c.user, &c.pass),
+                None => proxy,
+            })
+        }
+    })
+}
+
+/// Build the client and send one probe request at the given health level: strict does not
+/// follow redirects and fails a response slower than `check.max_latency`
+async fn checked_probe(
+    builder: reqwest::ClientBuilder,
+    url: &str,
+    timeout: Duration,
+    check: &ResponseCheck,
+    health: HealthLevel,
+) -> ProbeResult {
+    let builder = match health {
+        HealthLevel::Lenient => builder,
+        HealthLevel::Strict => builder.redirect(reqwest::redirect::Policy::none()),
+    };
+    let client = match builder.build() {
+        Ok(c) => c,
+        Err(e) => {
+            tracing::debug!("Failed to build HTTP client: {}", e);
+            return Err(ProbeFailure::Request);
+        }
+    };
+    let start = Instant::now();
+    send_probe(&client, url, timeout, check, health).await?;
+    if health == HealthLevel::Strict && start.elapsed() > check.max_latency {
+        tracing::debug!(
+            "NCSI probe: {} ms exceeds the strict health limit of {} ms",
+            start.elapsed().as_millis(),
+            check.max_latency.as_millis()
+        );
+        return Err(ProbeFailure::Slow);
+    }
+    Ok(())
+}
+
+/// Send one probe request; Ok when the status (and, for GET, the body) fits the probe style
+/// and the expected header, if any, is present. HEAD falls back to GET when the server
+/// answers 405 Method Not Allowed; strict health always uses GET to see the body.
+async fn send_probe(
+    client: &reqwest::Client,
+    url: &str,
+    timeout: Duration,
+    check: &ResponseCheck,
+    health: HealthLevel,
+) -> ProbeResult {
+    let style = health.style(check.style.for_url(url));
+    let passes = |r: &reqwest::Response| {
+        if r.status() == reqwest::StatusCode::PROXY_AUTHENTICATION_REQUIRED {
+            tracing::debug!(
+                "NCSI probe: 407 from the proxy (missing or rejected --proxy-user/--proxy-pass)"
+            );
+            Err(ProbeFailure::Status)
+        } else if !style.status_ok(r.status()) {
+            tracing::debug!("NCSI probe: unexpected status {}", r.status());
+            Err(ProbeFailure::Status)
+        } else if !check.expect_header.as_ref().is_none_or(|h| h.matches(r)) {
+            Err(ProbeFailure::Header)
+        } else {
+            Ok(())
+        }
+    };
+    if check.method == ProbeMethod::Head && health == HealthLevel::Lenient {
+        match client.head(url).timeout(timeout).send().await {
+            Ok(r) if r.status() == reqwest::StatusCode::METHOD_NOT_ALLOWED => {
+                tracing::debug!("{} does not allow HEAD, falling back to GET", url);
+            }
+            Ok(r) => return passes(&r),
+            Err(e) => return Err(ProbeFailure::from_error(&e)),
+        }
+    }
+    let response = match client.get(url).timeout(timeout).send().await {
+        Ok(r) => {
+            passes(&r)?;
+            r
+        }
+        Err(e) => return Err(ProbeFailure::from_error(&e)),
+    };
+    if matches!(style, ProbeStyle::Auto | ProbeStyle::Custom) {
+        return Ok(());
+    }
+    match response.text().await {
+        Ok(body) if style.body_ok(&body) => Ok(()),
+        Ok(_) => Err(ProbeFailure::Body),
+        Err(e) => Err(ProbeFailure::from_error(&e)),
+    }
+}
+
+/// Probe network reachability using the given NCSI endpoint, from `source` if given
+pub async fn test_network(
+    url: &str,
+    timeout: Duration,
+    proxy: &ProxyMode,
+    check: &ResponseCheck,
+    health: HealthLevel,
+    source: Option<IpAddr>,
+) -> ProbeResult {
+    tracing::debug!(
+        "Requesting NCSI: {} (timeout {} ms)",
+        url,
+        timeout.as_millis()
+    );
+    let builder = match client_builder(proxy) {
+        Ok(b) => b.local_address(source),
+        Err(e) => {
+            tracing::debug!("Failed to build HTTP client: {}", e);
+            return Err(ProbeFailure::Request);
+        }
+    };
+    let result = checked_probe(builder, url, timeout, check, health).await;
+    match result {
+        Ok(()) => tracing::debug!("NCSI probe: OK"),
+        Err(kind) => tracing::debug!("NCSI probe: failed ({})", kind),
     }
     result
 }
+
+/// First IPv4 address of the URL's host via the system resolver
+async fn resolve_ipv4(url: &str) -> Option<Ipv4Addr> {
+    let parsed = reqwest::Url::parse(url).ok()?;
+    let host = parsed.host_str()?;
+    let port = parsed.port_or_known_default()?;
+    let mut addrs = tokio::net::lookup_host((host, port)).await.ok()?;
+    addrs.find_map(|addr| match addr.ip() {
+        IpAddr::V4(v4) => Some(v4),
+        IpAddr::V6(_) => None,
+    })
+}
+
+/// Resolve `host` through the given DNS server instead of the system resolver
+async fn resolve_via(dns: IpAddr, host: &str) -> anyhow::Result<IpAddr> {
+    let config = ResolverConfig::from_parts(
+        None,
+        vec![],
+        NameServerConfigGroup::from_ips_clear(&[dns], 53, true),
+    );
+    let resolver = TokioAsyncResolver::tokio(config, ResolverOpts::default());
+    let lookup = resolver.lookup_ip(host).await?;
+    lookup
+        .iter()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("{} returned no address for {}", dns, host))
+}
+
+/// Probe the NCSI endpoint with its host resolved via `dns` (system resolver bypassed)
+pub async fn test_network_via_dns(
+    url: &str,
+    timeout: Duration,
+    dns: IpAddr,
+    proxy: &ProxyMode,
+    check: &ResponseCheck,
+    health: HealthLevel,
+) -> ProbeResult {
+    let parsed = match reqwest::Url::parse(url) {
+        Ok(u) => u,
+        Err(e) => {
+            tracing::debug!("Invalid NCSI URL {}: {}", url, e);
+            return Err(ProbeFailure::Request);
+        }
+    };
+    let (Some(host), Some(port)) = (parsed.host_str(), parsed.port_or_known_default()) else {
+        tracing::debug!("NCSI URL {} has no host/port", url);
+        return Err(ProbeFailure::Request);
+    };
+    let ip = match resolve_via(dns, host).await {
+        Ok(ip) => ip,
+        Err(e) => {
+            tracing::debug!("Resolving {} via {} failed: {}", host, dns, e);
+            return Err(ProbeFailure::Dns);
+        }
+    };
+    tracing::debug!(
+        "Requesting NCSI: {} ({} -> {} via DNS {}, timeout {} ms)",
+        url,
+        host,
+        ip,
+        dns,
+        timeout.as_millis()
+    );
+    let builder = match client_builder(proxy) {
+        Ok(b) => b.resolve(host, SocketAddr::new(ip, port)),
+        Err(e) => {
+            tracing::debug!("Failed to build HTTP client: {}", e);
+            return Err(ProbeFailure::Request);
+        }
+    };
+    checked_probe(builder, url, timeout, check, health).await
+}
+
+/// Pseudo-random duration in [0, max), seeded from the clock (good enough to spread retries)
+fn jitter(max: Duration) -> Duration {
+    let max_ms = max.as_millis() as u64;
+    if max_ms == 0 {
+        return Duration::ZERO;
+    }
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    Duration::from_millis(nanos as u64 % max_ms)
+}
+
+/// Weight of the newest sample in an endpoint's latency EWMA
+const LATENCY_EWMA_ALPHA: f64 = 0.3;
+
+/// With several endpoints, probe all of them every this many probes to refresh their latency
+const REMEASURE_EVERY: u32 = 20;
+
+/// Successful probe round-trips kept for `RttStats`
+const RTT_HISTORY: usize = 32;
+
+/// Direction of the recent round-trips compared with the older ones
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RttTrend {
+    Improving,
+    Steady,
+    Worsening,
+}
+
+/// Summary of the last `RTT_HISTORY` successful probe round-trips (shown by `--status`)
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct RttStats {
+    pub samples: usize,
+    pub min_ms: u64,
+    pub max_ms: u64,
+    pub avg_ms: u64,
+    pub p95_ms: u64,
+    pub trend: RttTrend,
+}
+
+impl RttStats {
+    /// Stats over `samples`, oldest first. The trend compares the averages of the newer and
+    /// older halves: more than 20% apart is improving/worsening (steady below 8 samples).
+    fn from_samples(samples: &VecDeque<Duration>) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let ms: Vec<u64> = samples.iter().map(|d| d.as_millis() as u64).collect();
+        let mut sorted = ms.clone();
+        sorted.sort_unstable();
+        let avg = |v: &[u64]| v.iter().sum::<u64>() / v.len().max(1) as u64;
+        let (older, newer) = ms.split_at(ms.len() / 2);
+        let trend = if ms.len() < 8 {
+            RttTrend::Steady
+        } else {
+            let (older, newer) = (avg(older) as f64, avg(newer) as f64);
+            if newer > older * 1.2 {
+                RttTrend::Worsening
+            } else if newer < older * 0.8 {
+                RttTrend::Improving
+            } else {
+                RttTrend::Steady
+            }
+        };
+        Some(Self {
+            samples: ms.len(),
+            min_ms: sorted[0],
+            max_ms: sorted[sorted.len() - 1],
+            avg_ms: avg(&ms),
+            // Nearest rank
+            p95_ms: sorted[(sorted.len() * 95).div_ceil(100) - 1],
+            trend,
+        })
+    }
+}
+
+/// One NCSI endpoint and its smoothed latency (None until first measured)
+struct Endpoint {
+    url: String,
+    ewma_ms: Option<f64>,
+}
+
+/// Run `checks` concurrently, at most `limit` at a time (None: all at once), and collect their
+/// results in completion order. Once one returns `stop_on`, the rest are dropped (cancelled).
+async fn fan_out<F>(checks: Vec<F>, limit: Option<usize>, stop_on: Option<bool>) -> Vec<bool>
+where
+    F: std::future::Future<Output = bool>,
+{
+    let semaphore = Semaphore::new(limit.unwrap_or(Semaphore::MAX_PERMITS));
+    let mut pending: FuturesUnordered<_> = checks
+        .into_iter()
+        .map(|check| {
+            let semaphore = &semaphore;
+            async move {
+                // Never closed, so acquire only waits for a free permit
+                let _permit = semaphore.acquire().await;
+                check.await
+            }
+        })
+        .collect();
+    let mut results = Vec::new();
+    while let Some(ok) = pending.next().await {
+        results.push(ok);
+        if Some(ok) == stop_on {
+            break;
+        }
+    }
+    results
+}
+
+/// NCSI prober: endpoint settings plus a short-lived result cache, shared by the
+/// loop probe and the post-connect checks during recovery.
+/// With several endpoints, routine probes go to the fastest (lowest latency EWMA); when it
+/// fails the others are requested concurrently (up to `--probe-concurrency` at a time) and
+/// the first to pass wins.
+pub struct Prober {
+    endpoints: Mutex<Vec<Endpoint>>,
+    probe_count: AtomicU32,
+    best_url: Mutex<Option<String>>,
+    timeout: Duration,
+    cache_ttl: Duration,
+    /// Latest uncached result and why it failed, for the cache
+    last: Mutex<Option<(Instant, bool, Option<ProbeFailure>)>>,
+    rtts: Mutex<VecDeque<Duration>>,
+    last_failure: Mutex<Option<ProbeFailure>>,
+    probe_dns: Option<IpAddr>,
+    proxy: ProxyMode,
+    check: ResponseCheck,
+    health: HealthLevel,
+    retries: u32,
+    retry_delay: Duration,
+    simulate_down: AtomicU32,
+    simulated: AtomicBool,
+    diagnose_dns: bool,
+    wifi_source: bool,
+    wifi_bind: bool,
+    use_nlm: bool,
+    protocols: Vec<ProbeProtocol>,
+    combine: ProbeCombine,
+    concurrency: Option<usize>,
+}
+
+impl Prober {
+    /// `cache_ttl_ms` = 0 disables caching (every call hits the endpoint)
+    pub fn new(urls: &[String], timeout: Duration, cache_ttl_ms: u64) -> Self {
+        Self {
+            endpoints: Mutex::new(
+                urls.iter()
+                    .map(|url| Endpoint {
+                        url: url.clone(),
+                        ewma_ms: None,
+                    })
+                    .collect(),
+            ),
+            probe_count: AtomicU32::new(0),
+            best_url: Mutex::new(None),
+            timeout,
+            cache_ttl: Duration::from_millis(cache_ttl_ms),
+            last: Mutex::new(None),
+            rtts: Mutex::new(VecDeque::with_capacity(RTT_HISTORY)),
+            last_failure: Mutex::new(None),
+            probe_dns: None,
+            proxy: ProxyMode::System,
+            check: ResponseCheck::default(),
+            health: HealthLevel::Lenient,
+            retries: 0,
+            retry_delay: Duration::ZERO,
+            simulate_down: AtomicU32::new(0),
+            simulated: AtomicBool::new(false),
+            diagnose_dns: false,
+            wifi_source: false,
+            wifi_bind: false,
+            use_nlm: false,
+            protocols: vec![ProbeProtocol::Http],
+            combine: ProbeCombine::All,
+            concurrency: None,
+        }
+    }
+
+    /// Proxy handling for all probe requests (default: system proxy)
+    pub fn with_proxy(mut self, proxy: ProxyMode) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// HTTP method for all probe requests (default: GET)
+    pub fn with_method(mut self, method: ProbeMethod) -> Self {
+        self.check.method = method;
+        self
+    }
+
+    /// Require this response header (as well as a success status) before calling the network up
+    pub fn with_expected_header(mut self, header: Option<ExpectedHeader>) -> Self {
+        self.check.expect_header = header;
+        self
+    }
+
+    /// What counts as a successful response (default: auto-detect generate_204 URLs)
+    pub fn with_style(mut self, style: ProbeStyle) -> Self {
+        self.check.style = style;
+        self
+    }
+
+    /// Health level for HTTP probes; `max_latency` only applies to strict
+    pub fn with_health(mut self, health: HealthLevel, max_latency: Duration) -> Self {
+        self.health = health;
+        self.check.max_latency = max_latency;
+        self
+    }
+
+    /// When the normal probe fails, retry with the NCSI host resolved via this DNS server
+    pub fn with_probe_dns(mut self, dns: Option<IpAddr>) -> Self {
+        self.probe_dns = dns;
+        self
+    }
+
+    /// Retry a failed request up to `retries` times, waiting `delay_ms` plus up to 50% jitter
+    pub fn with_retries(mut self, retries: u32, delay_ms: u64) -> Self {
+        self.retries = retries;
+        self.retry_delay = Duration::from_millis(delay_ms);
+        self
+    }
+
+    /// When a probe fails, also try a raw IP address to tell DNS failure from no connectivity
+    pub fn with_dns_diagnosis(mut self, enabled: bool) -> Self {
+        self.diagnose_dns = enabled;
+        self
+    }
+
+    /// Send probes from the Wi-Fi adapter's address so a VPN split tunnel can't answer for it
+    pub fn with_wifi_source(mut self, enabled: bool) -> Self {
+        self.wifi_source = enabled;
+        self
+    }
+
+    /// Like `with_wifi_source`, but while Wi-Fi has no address yet probe unbound instead of
+    /// counting the probe as failed
+    pub fn with_wifi_bind(mut self, enabled: bool) -> Self {
+        self.wifi_bind = enabled;
+        self
+    }
+
+    /// Ask the Network List Manager first and skip the HTTP probe when it reports internet
+    pub fn with_nlm(mut self, enabled: bool) -> Self {
+        self.use_nlm = enabled;
+        self
+    }
+
+    /// Check with these protocols (HTTP is the endpoint probe) and combine their results
+    pub fn with_protocols(mut self, protocols: &[ProbeProtocol], combine: ProbeCombine) -> Self {
+        self.protocols.clear();
+        for p in protocols {
+            if !self.protocols.contains(p) {
+                self.protocols.push(*p);
+            }
+        }
+        self.combine = combine;
+        self
+    }
+
+    /// At most `limit` probe requests or protocol checks in flight at once (None: unbounded)
+    pub fn with_concurrency(mut self, limit: Option<usize>) -> Self {
+        self.concurrency = limit;
+        self
+    }
+
+    /// Report the first `count` probes as failed regardless of reality (`--simulate-down`)
+    pub fn with_simulated_down(self, count: u32) -> Self {
+        self.simulate_down.store(count, Ordering::Relaxed);
+        self
+    }
+
+    /// Request another URL with this prober's timeout, proxy and retry settings (uncached)
+    pub async fn probe_url(&self, url: &str) -> bool {
+        self.request_with_retries(url, None).await
+    }
+
+    /// Whether the latest `probe` was a `--simulate-down` failure (nothing was requested)
+    pub fn simulated(&self) -> bool {
+        self.simulated.load(Ordering::Relaxed)
+    }
+
+    /// Probe reachability, reusing the last result if it is younger than the cache TTL
+    pub async fn probe(&self) -> bool {
+        // Every return below sets a fresh category (or none), so no stale one outlives its probe
+        *self.last_failure.lock().unwrap() = None;
+        let simulated =
+            self.simulate_down
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+        self.simulated.store(simulated.is_ok(), Ordering::Relaxed);
+        if let Ok(n) = simulated {
+            tracing::warn!(
+                "NCSI probe: simulated failure (--simulate-down, {} left)",
+                n - 1
+            );
+            return false;
+        }
+        // Only "internet access" is taken as is: Windows re-probes a fresh connection up to a
+        // minute late, so "no internet" right after a connect is confirmed over HTTP
+        if self.check.method == ProbeMethod::Wininet {
+            match nlm::os_connectivity() {
+                Ok(true) => {
+                    tracing::debug!("NCSI probe: Windows reports internet access");
+                    return true;
+                }
+                Ok(false) => {
+                    tracing::debug!("Windows reports no internet (yet), probing HTTP");
+                }
+                Err(e) => {
+                    tracing::debug!("Windows connectivity query failed ({}), probing HTTP", e);
+                }
+            }
+        }
+        // A local query, no round-trip; anything but "internet" falls through to the probe
+        if self.use_nlm && nlm::has_internet() == Some(true) {
+            tracing::debug!("NCSI probe: skipped, Network List Manager reports internet");
+            return true;
+        }
+        if !self.cache_ttl.is_zero() {
+            if let Some((at, ok, failure)) = *self.last.lock().unwrap() {
+                if at.elapsed() < self.cache_ttl {
+                    *self.last_failure.lock().unwrap() = failure;
+                    tracing::debug!(
+                        "NCSI probe: cached result {} ({} ms old)",
+                        if ok { "OK" } else { "failed" },
+                        at.elapsed().as_millis()
+                    );
+                    return ok;
+                }
+            }
+        }
+
+        let ok = self.probe_uncached().await;
+        if !self.cache_ttl.is_zero() {
+            *self.last.lock().unwrap() = Some((Instant::now(), ok, self.last_failure()));
+        }
+        ok
+    }
+
+    /// test_network, retried with jittered delays so one lost packet does not count as down
+    async fn request_with_retries(&self, url: &str, source: Option<IpAddr>) -> bool {
+        for attempt in 0..=self.retries {
+            if attempt > 0 {
+                let delay = self.retry_delay + jitter(self.retry_delay / 2);
+                tracing::debug!(
+                    "NCSI retry {}/{} in {} ms",
+                    attempt,
+                    self.retries,
+                    delay.as_millis()
+                );
+                tokio::time::sleep(delay).await;
+            }
+            match test_network(
+                url,
+                self.timeout,
+                &self.proxy,
+                &self.check,
+                self.health,
+                source,
+            )
+            .await
+            {
+                Ok(()) => return true,
+                Err(kind) => *self.last_failure.lock().unwrap() = Some(kind),
+            }
+        }
+        false
+    }
+
+    /// Why the latest uncached HTTP probe failed (None after a pass, or for non-HTTP failures)
+    pub fn last_failure(&self) -> Option<ProbeFailure> {
+        *self.last_failure.lock().unwrap()
+    }
+
+    /// Endpoint URLs, fastest first; unmeasured endpoints sort first so they get measured
+    fn endpoints_by_latency(&self) -> Vec<String> {
+        let endpoints = self.endpoints.lock().unwrap();
+        let mut order: Vec<_> = endpoints
+            .iter()
+            .map(|e| (e.ewma_ms.unwrap_or(0.0), e.url.clone()))
+            .collect();
+        order.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let order: Vec<String> = order.into_iter().map(|(_, url)| url).collect();
+
+        let mut best = self.best_url.lock().unwrap();
+        if order.len() > 1 && best.as_deref() != order.first().map(String::as_str) {
+            let ewma = endpoints
+                .iter()
+                .find(|e| e.url == order[0])
+                .and_then(|e| e.ewma_ms);
+            tracing::debug!(
+                "NCSI endpoint selected: {} (latency EWMA: {})",
+                order[0],
+                ewma.map_or("unmeasured".to_string(), |ms| format!("{:.0} ms", ms))
+            );
+            *best = Some(order[0].clone());
+        }
+        order
+    }
+
+    /// `timed_request`, logging a failure when there are other endpoints to fall back to
+    async fn endpoint_request(&self, url: &str, source: Option<IpAddr>, several: bool) -> bool {
+        let ok = self.timed_request(url, source).await;
+        if !ok && several {
+            tracing::debug!("NCSI endpoint {} failed", url);
+        }
+        ok
+    }
+
+    /// Request `url` and fold its latency into the endpoint's EWMA (failures count as a timeout)
+    async fn timed_request(&self, url: &str, source: Option<IpAddr>) -> bool {
+        let start = Instant::now();
+        let ok = self.request_with_retries(url, source).await;
+        let sample_ms = if ok {
+            let mut rtts = self.rtts.lock().unwrap();
+            if rtts.len() == RTT_HISTORY {
+                rtts.pop_front();
+            }
+            rtts.push_back(start.elapsed());
+            start.elapsed().as_secs_f64() * 1000.0
+        } else {
+            self.timeout.as_secs_f64() * 1000.0
+        };
+        if let Some(e) = self
+            .endpoints
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .find(|e| e.url == url)
+        {
+            e.ewma_ms = Some(match e.ewma_ms {
+                Some(prev) => prev + LATENCY_EWMA_ALPHA * (sample_ms - prev),
+                None => sample_ms,
+            });
+        }
+        ok
+    }
+
+    /// Min/max/avg/p95 and trend of the recent successful HTTP probe round-trips
+    pub fn rtt_stats(&self) -> Option<RttStats> {
+        RttStats::from_samples(&self.rtts.lock().unwrap())
+    }
+
+    /// `--probe-wifi-source`/`--probe-bind-wifi`: the connected Wi-Fi interface's IPv4 address
+    /// to send probes from, with a warning when the route to `url` leaves through another
+    /// (VPN/virtual) interface. None when Wi-Fi has no address.
+    async fn wifi_source_address(&self, url: Option<&str>) -> Option<IpAddr> {
+        let (iface, addr) = wlan::connected_interface_address()?;
+        let dest = match url {
+            Some(url) => resolve_ipv4(url).await,
+            None => None,
+        };
+        if let Some(dest) = dest {
+            if route::egress_via(&iface, dest) == Some(false) {
+                tracing::warn!(
+                    "Route to {} leaves through another interface (VPN or virtual adapter?); \
+                     probing from the Wi-Fi address {} instead",
+                    dest,
+                    addr
+                );
+            }
+        }
+        Some(IpAddr::V4(addr))
+    }
+
+    /// Run each configured protocol against the fastest endpoint and combine the results
+    async fn probe_uncached(&self) -> bool {
+        if self.protocols == [ProbeProtocol::Http] {
+            return self.probe_http().await;
+        }
+        let url = self
+            .endpoints_by_latency()
+            .into_iter()
+            .next()
+            .unwrap_or_default();
+        let timeout = self.timeout;
+        let url = url.as_str();
+        let checks = self
+            .protocols
+            .iter()
+            .map(|&protocol| async move {
+                let ok = match probes::for_protocol(protocol) {
+                    Some(probe) => probe.check(url, timeout).await,
+                    None => self.probe_http().await,
+                };
+                tracing::debug!("{:?} probe: {}", protocol, if ok { "OK" } else { "failed" });
+                ok
+            })
+            .collect();
+        // Stop at the first result that settles the verdict (a failure for all, a pass for any)
+        let results = fan_out(checks, self.concurrency, Some(self.combine.decisive())).await;
+        self.combine.combine(&results)
+    }
+
+    /// HTTP probe: endpoints in latency order, then the DNS fallback and diagnosis
+    async fn probe_http(&self) -> bool {
+        let order = self.endpoints_by_latency();
+        let source = if self.wifi_source || self.wifi_bind {
+            match self
+                .wifi_source_address(order.first().map(String::as_str))
+                .await
+            {
+                Some(ip) => Some(ip),
+                None if self.wifi_source => {
+                    tracing::info!(
+                        "NCSI probe: Wi-Fi has no IPv4 address (--probe-wifi-source), \
+                         counting as down"
+                    );
+                    return false;
+                }
+                None => {
+                    tracing::info!(
+                        "NCSI probe: Wi-Fi has no IPv4 address yet (--probe-bind-wifi), \
+                         probing without binding"
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let remeasure = order.len() > 1
+            && self
+                .probe_count
+                .fetch_add(1, Ordering::Relaxed)
+                .is_multiple_of(REMEASURE_EVERY);
+        // Remeasuring requests every endpoint; otherwise the fastest alone, then the fallbacks
+        // together with the first pass winning
+        let (first, rest) = if remeasure {
+            (&order[..0], &order[..])
+        } else {
+            order.split_at(order.len().min(1))
+        };
+        let several = order.len() > 1;
+        let mut any_ok = match first.first() {
+            Some(url) => self.endpoint_request(url, source, several).await,
+            None => false,
+        };
+        if !any_ok || remeasure {
+            let requests = rest
+                .iter()
+                .map(|url| self.endpoint_request(url, source, several))
+                .collect();
+            let stop_on = (!remeasure).then_some(true);
+            any_ok |= fan_out(requests, self.concurrency, stop_on)
+                .await
+                .contains(&true);
+        }
+        if any_ok {
+            return true;
+        }
+        let Some(dns) = self.probe_dns else {
+            self.diagnose_failure().await;
+            return false;
+        };
+        let Some(url) = order.first() else {
+            return false;
+        };
+        if test_network_via_dns(
+            url,
+            self.timeout,
+            dns,
+            &self.proxy,
+            &self.check,
+            self.health,
+        )
+        .await
+        .is_ok()
+        {
+            tracing::warn!(
+                "NCSI failed with system DNS but succeeded via DNS {}: system DNS appears broken \
+                 (treating network as up; Wi-Fi recovery would not fix this)",
+                dns
+            );
+            return true;
+        }
+        tracing::debug!("NCSI probe via DNS {}: failed too", dns);
+        self.diagnose_failure().await;
+        false
+    }
+
+    /// `--diagnose-dns`: after a failed probe, check a raw IP and log which kind of outage it is
+    async fn diagnose_failure(&self) {
+        if !self.diagnose_dns {
+            return;
+        }
+        if reachable(RAW_IP_PROBE_URL, self.timeout, &self.proxy).await {
+            tracing::warn!(
+                "NCSI failed but raw IP {} is reachable: likely a DNS issue",
+                RAW_IP_PROBE_URL
+            );
+        } else {
+            tracing::info!(
+                "NCSI failed and raw IP {} is unreachable too: no connectivity",
+                RAW_IP_PROBE_URL
+            );
+        }
+    }
+}
+
+/// Hysteresis over the last `size` probe results (`--probe-window`, `--down-threshold`,
+/// `--up-threshold`): the verdict turns down once `down_threshold` of them failed and back up
+/// once `up_threshold` of them passed. With the defaults (1/1/1) it follows every probe.
+pub struct ProbeWindow {
+    results: VecDeque<bool>,
+    size: usize,
+    down_threshold: usize,
+    up_threshold: usize,
+    up: bool,
+}
+
+impl ProbeWindow {
+    pub fn new(size: usize, down_threshold: usize, up_threshold: usize) -> Self {
+        Self {
+            results: VecDeque::with_capacity(size),
+            size,
+            down_threshold,
+            up_threshold,
+            up: true,
+        }
+    }
+
+    /// Add a probe result and return the smoothed verdict (true = up)
+    pub fn record(&mut self, ok: bool) -> bool {
+        if self.results.len() == self.size {
+            self.results.pop_front();
+        }
+        self.results.push_back(ok);
+        let passed = self.results.iter().filter(|&&r| r).count();
+        let failed = self.results.len() - passed;
+        if self.up && failed >= self.down_threshold {
+            self.up = false;
+        } else if !self.up && passed >= self.up_threshold {
+            self.up = true;
+        }
+        self.up
+    }
+
+    /// "passed/failed of the last N" for log lines
+    pub fn summary(&self) -> String {
+        let passed = self.results.iter().filter(|&&r| r).count();
+        format!(
+            "{} passed, {} failed of the last {}",
+            passed,
+            self.results.len() - passed,
+            self.results.len()
+        )
+    }
+
+    pub fn up_threshold(&self) -> usize {
+        self.up_threshold
+    }
+
+    pub fn down_threshold(&self) -> usize {
+        self.down_threshold
+    }
+
+    /// Start over as up, e.g. after a recovery round verified the connection
+    pub fn reset(&mut self) {
+        self.results.clear();
+        self.up = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    /// A check that passes with `ok` after yielding, tracking the most checks in flight
+    async fn check(ok: bool, running: &AtomicUsize, peak: &AtomicUsize) -> bool {
+        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+        peak.fetch_max(now, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        running.fetch_sub(1, Ordering::SeqCst);
+        ok
+    }
+
+    #[tokio::test]
+    async fn fan_out_respects_the_limit() {
+        let (running, peak) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        let checks = (0..6).map(|_| check(false, &running, &peak)).collect();
+        let results = fan_out(checks, Some(2), None).await;
+        assert_eq!(results, [false; 6]);
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn fan_out_is_unbounded_by_default() {
+        let (running, peak) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        let checks = (0..6).map(|_| check(true, &running, &peak)).collect();
+        let results = fan_out(checks, None, None).await;
+        assert_eq!(results, [true; 6]);
+        assert_eq!(peak.load(Ordering::SeqCst), 6);
+    }
+
+    #[tokio::test]
+    async fn fan_out_stops_at_the_decisive_result() {
+        let (running, peak) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        let checks = [false, false, true, false, false]
+            .into_iter()
+            .map(|ok| check(ok, &running, &peak))
+            .collect();
+        // One at a time, so completion order is input order
+        let results = fan_out(checks, Some(1), Some(true)).await;
+        assert_eq!(results, [false, false, true]);
+    }
+}
@@ -0,0 +1,38 @@
+//! Windows' own connectivity verdict via the Network List Manager (`--use-nlm`)
+
+use windows::Networking::Connectivity::{NetworkConnectivityLevel, NetworkInformation};
+use windows::Win32::Networking::NetworkListManager::{
+    INetworkListManager, NetworkListManager, NLM_CONNECTIVITY_IPV4_INTERNET,
+    NLM_CONNECTIVITY_IPV6_INTERNET,
+};
+use windows::Win32::System::Com::{
+    CoCreateInstance, CoInitializeEx, CLSCTX_ALL, COINIT_MULTITHREADED,
+};
+
+/// Whether NLM reports internet connectivity over IPv4 or IPv6 (what the taskbar shows as
+/// connected, as opposed to "No Internet" / limited). None if NLM cannot be queried.
+pub fn has_internet() -> Option<bool> {
+    unsafe {
+        // Joins the thread's existing apartment if there is one; failure shows up below
+        let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+        let nlm: INetworkListManager =
+            CoCreateInstance(&NetworkListManager, None, CLSCTX_ALL).ok()?;
+        let connectivity = nlm.GetConnectivity().ok()?;
+        let internet = NLM_CONNECTIVITY_IPV4_INTERNET.0 | NLM_CONNECTIVITY_IPV6_INTERNET.0;
+        Some(connectivity.0 & internet != 0)
+    }
+}
+
+/// `--probe-method wininet`: Windows' connectivity level for the internet connection profile,
+/// the same NCSI verdict behind the taskbar network icon. True only for internet access (not
+/// local-only or captive portal); no internet profile at all counts as down. Err when the API
+/// fails, so the caller can fall back to its own HTTP probe.
+pub fn os_connectivity() -> windows::core::Result<bool> {
+    let profile = match NetworkInformation::GetInternetConnectionProfile() {
+        Ok(profile) => profile,
+        // A successful call returning null: there is no internet connection profile
+        Err(e) if e.code().is_ok() => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    Ok(profile.GetNetworkConnectivityLevel()? == NetworkConnectivityLevel::InternetAccess)
+}
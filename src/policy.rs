@@ -0,0 +1,35 @@
+//! Pre-check for Wi-Fi being blocked by Group Policy / MDM (recovery cannot work then)
+
+use crate::{registry, wlan};
+
+/// MDM Wi-Fi CSP: AllowWiFi = 0 disables Wi-Fi entirely
+const MDM_WIFI_KEY: &str = r"SOFTWARE\Microsoft\PolicyManager\current\device\Wifi";
+
+/// Group Policy "Prohibit connection to non-domain networks when connected to domain authenticated network"
+const WCM_GROUP_POLICY_KEY: &str = r"SOFTWARE\Policies\Microsoft\Windows\WcmSvc\GroupPolicy";
+
+/// Return the reasons Wi-Fi recovery is blocked by policy (empty if none detected)
+pub fn wifi_policy_blocks() -> Vec<String> {
+    let mut reasons = Vec::new();
+
+    if registry::read_hklm_dword(MDM_WIFI_KEY, "AllowWiFi") == Some(0) {
+        reasons.push("Wi-Fi disabled by MDM policy (Wifi/AllowWiFi = 0)".to_string());
+    }
+    if registry::read_hklm_dword(WCM_GROUP_POLICY_KEY, "fBlockNonDomain") == Some(1) {
+        reasons.push(
+            "Group Policy blocks non-domain networks while a domain network is connected \
+             (WcmSvc fBlockNonDomain = 1)"
+                .to_string(),
+        );
+    }
+    match wlan::autoconf_disabled_interfaces() {
+        Ok(0) => {}
+        Ok(n) => reasons.push(format!(
+            "WLAN AutoConfig disabled on {} interface(s) (netsh wlan set autoconfig)",
+            n
+        )),
+        Err(e) => tracing::debug!("AutoConfig state query failed: {}", e),
+    }
+
+    reasons
+}
@@ -0,0 +1,54 @@
+//! Power-source condition for recovery (`--recover-on battery|ac`)
+
+use windows::Win32::System::Power::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+
+/// When recovery may run, by power source
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum RecoverOn {
+    /// Regardless of power source
+    #[default]
+    Always,
+    /// Only on battery (roaming laptop)
+    Battery,
+    /// Only on AC power (docked)
+    Ac,
+}
+
+/// Where the machine currently draws power from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PowerSource {
+    Ac,
+    Battery,
+}
+
+/// Current power source; None when Windows reports it as unknown or the query fails
+pub fn power_source() -> Option<PowerSource> {
+    let mut status = SYSTEM_POWER_STATUS::default();
+    unsafe { GetSystemPowerStatus(&mut status) }.ok()?;
+    match status.ACLineStatus {
+        0 => Some(PowerSource::Battery),
+        1 => Some(PowerSource::Ac),
+        _ => None,
+    }
+}
+
+impl RecoverOn {
+    /// Whether recovery may run on `source`; an unknown power state always allows it
+    pub fn allows(self, source: Option<PowerSource>) -> bool {
+        match (self, source) {
+            (RecoverOn::Always, _) | (_, None) => true,
+            (RecoverOn::Battery, Some(source)) => source == PowerSource::Battery,
+            (RecoverOn::Ac, Some(source)) => source == PowerSource::Ac,
+        }
+    }
+}
+
+impl std::fmt::Display for RecoverOn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            RecoverOn::Always => "always",
+            RecoverOn::Battery => "on battery",
+            RecoverOn::Ac => "on AC power",
+        })
+    }
+}
@@ -0,0 +1,168 @@
+//! Connectivity checks besides the HTTP probe (`--probe-protocol dns,tcp,ping`), combined
+//! with it under `--probe-combine`
+
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr};
+use std::pin::Pin;
+use std::time::Duration;
+
+use windows::Win32::NetworkManagement::IpHelper::{
+    IcmpCloseHandle, IcmpCreateFile, IcmpSendEcho, ICMP_ECHO_REPLY,
+};
+
+/// Connectivity check kinds for `--probe-protocol`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ProbeProtocol {
+    /// HTTP request to the NCSI endpoint(s), the default probe
+    Http,
+    /// Resolve the probe host with the system resolver
+    Dns,
+    /// Open a TCP connection to the probe host and port
+    Tcp,
+    /// ICMP echo to the probe host (IPv4)
+    Ping,
+}
+
+/// How per-protocol results combine into the verdict (`--probe-combine`)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ProbeCombine {
+    /// Up only if every protocol passes
+    #[default]
+    All,
+    /// Up if any protocol passes
+    Any,
+}
+
+impl ProbeCombine {
+    pub fn combine(self, results: &[bool]) -> bool {
+        match self {
+            ProbeCombine::All => results.iter().all(|ok| *ok),
+            ProbeCombine::Any => results.iter().any(|ok| *ok),
+        }
+    }
+
+    /// The single result that settles the verdict without waiting for the rest: one failure
+    /// under `All`, one pass under `Any`
+    pub fn decisive(self) -> bool {
+        self == ProbeCombine::Any
+    }
+}
+
+pub type ProbeFuture<'a> = Pin<Box<dyn Future<Output = bool> + Send + 'a>>;
+
+/// A connectivity check against the host of the probe URL
+pub trait NetworkProbe: Send + Sync {
+    /// Whether the check passes for `url` within `timeout`
+    fn check<'a>(&'a self, url: &'a str, timeout: Duration) -> ProbeFuture<'a>;
+}
+
+/// The check for a non-HTTP protocol; HTTP is the prober's own endpoint logic (None)
+pub fn for_protocol(protocol: ProbeProtocol) -> Option<Box<dyn NetworkProbe>> {
+    match protocol {
+        ProbeProtocol::Http => None,
+        ProbeProtocol::Dns => Some(Box::new(DnsProbe)),
+        ProbeProtocol::Tcp => Some(Box::new(TcpProbe)),
+        ProbeProtocol::Ping => Some(Box::new(PingProbe)),
+    }
+}
+
+/// "host:port" of a probe URL (scheme default port if not given; IPv6 hosts stay bracketed)
+fn host_port(url: &str) -> Option<String> {
+    let parsed = reqwest::Url::parse(url).ok()?;
+    Some(format!(
+        "{}:{}",
+        parsed.host_str()?,
+        parsed.port_or_known_default()?
+    ))
+}
+
+/// Resolves the host through the system resolver
+struct DnsProbe;
+
+impl NetworkProbe for DnsProbe {
+    fn check<'a>(&'a self, url: &'a str, timeout: Duration) -> ProbeFuture<'a> {
+        Box::pin(async move {
+            let Some(target) = host_port(url) else {
+                return false;
+            };
+            match tokio::time::timeout(timeout, tokio::net::lookup_host(target)).await {
+                Ok(Ok(mut addrs)) => addrs.next().is_some(),
+                _ => false,
+            }
+        })
+    }
+}
+
+/// Connects to the host's port (no data sent)
+struct TcpProbe;
+
+impl NetworkProbe for TcpProbe {
+    fn check<'a>(&'a self, url: &'a str, timeout: Duration) -> ProbeFuture<'a> {
+        Box::pin(async move {
+            let Some(target) = host_port(url) else {
+                return false;
+            };
+            let connect = tokio::net::TcpStream::connect(target);
+            matches!(tokio::time::timeout(timeout, connect).await, Ok(Ok(_)))
+        })
+    }
+}
+
+/// One ICMP echo to the host's first IPv4 address
+struct PingProbe;
+
+impl NetworkProbe for PingProbe {
+    fn check<'a>(&'a self, url: &'a str, timeout: Duration) -> ProbeFuture<'a> {
+        Box::pin(async move {
+            let Some(target) = host_port(url) else {
+                return false;
+            };
+            let Ok(Ok(addrs)) =
+                tokio::time::timeout(timeout, tokio::net::lookup_host(target)).await
+            else {
+                return false;
+            };
+            let Some(addr) = addrs.map(|a| a.ip()).find_map(|ip| match ip {
+                IpAddr::V4(v4) => Some(v4),
+                IpAddr::V6(_) => None,
+            }) else {
+                return false;
+            };
+            // IcmpSendEcho blocks until the reply or the timeout
+            tokio::task::spawn_blocking(move || ping(addr, timeout))
+                .await
+                .unwrap_or(false)
+        })
+    }
+}
+
+/// Send one echo request; true if a reply with IP_SUCCESS came back within `timeout`
+fn ping(addr: Ipv4Addr, timeout: Duration) -> bool {
+    let payload = [0u8; 32];
+    // Reply buffer: one ICMP_ECHO_REPLY, the echoed payload and room for an ICMP error
+    let mut reply = vec![0u8; std::mem::size_of::<ICMP_ECHO_REPLY>() + payload.len() + 8];
+    unsafe {
+        let Ok(handle) = IcmpCreateFile() else {
+            return false;
+        };
+        let replies = IcmpSendEcho(
+            handle,
+            u32::from_ne_bytes(addr.octets()),
+            payload.as_ptr().cast(),
+            payload.len() as u16,
+            None,
+            reply.as_mut_ptr().cast(),
+            reply.len() as u32,
+            timeout.as_millis() as u32,
+        );
+        let _ = IcmpCloseHandle(handle);
+        // Status 0 is IP_SUCCESS
+        replies > 0
+            && reply
+                .as_ptr()
+                .cast::<ICMP_ECHO_REPLY>()
+                .read_unaligned()
+                .Status
+                == 0
+    }
+}
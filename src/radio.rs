@@ -1,13 +1,61 @@
 //! Wi-Fi Radio control (Windows.Devices.Radios)
 
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use windows::Devices::Radios::{Radio, RadioKind, RadioState};
+use windows::Win32::Foundation::{
+    ERROR_NOT_SUPPORTED, E_NOINTERFACE, E_NOTIMPL, REGDB_E_CLASSNOTREG,
+};
 
-/// Turn on Wi-Fi radio if currently off
-pub async fn turn_on_wifi_radio() -> anyhow::Result<()> {
+/// Set once the Radios API turned out to be missing; later rounds skip the radio step
+static API_UNAVAILABLE: AtomicBool = AtomicBool::new(false);
+
+/// Error: the Radios WinRT API is not available on this system (e.g. Windows Server),
+/// as opposed to a radio that exists but failed to toggle
+#[derive(Debug)]
+pub struct RadioApiUnavailable;
+
+impl std::fmt::Display for RadioApiUnavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Radio control API unavailable on this system")
+    }
+}
+
+impl std::error::Error for RadioApiUnavailable {}
+
+/// Whether a GetRadiosAsync failure means the API itself is missing
+fn is_api_unavailable(e: &windows::core::Error) -> bool {
+    let code = e.code();
+    code == REGDB_E_CLASSNOTREG
+        || code == E_NOTIMPL
+        || code == E_NOINTERFACE
+        || code == ERROR_NOT_SUPPORTED.to_hresult()
+}
+
+/// System radio list; a missing API is reported (once at warn level) as `RadioApiUnavailable`
+fn get_radios() -> anyhow::Result<windows::Foundation::Collections::IVectorView<Radio>> {
+    if API_UNAVAILABLE.load(Ordering::Relaxed) {
+        return Err(RadioApiUnavailable.into());
+    }
     tracing::info!("Getting system radio list...");
-    let op = Radio::GetRadiosAsync()?;
     // WinRT IAsyncOperation.get() must run on single thread; block here
-    let radios = op.get()?;
+    match Radio::GetRadiosAsync().and_then(|op| op.get()) {
+        Ok(radios) => Ok(radios),
+        Err(e) if is_api_unavailable(&e) => {
+            API_UNAVAILABLE.store(true, Ordering::Relaxed);
+            tracing::warn!(
+                "Radio control API unavailable ({}); skipping the radio step from now on",
+                e
+            );
+            Err(RadioApiUnavailable.into())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Turn on Wi-Fi radio if currently off
+pub async fn turn_on_wifi_radio() -> anyhow::Result<()> {
+    let radios = get_radios()?;
     let count = radios.Size()?;
     tracing::info!("Found {} radio(s)", count);
 
@@ -36,3 +84,32 @@ pub async fn turn_on_wifi_radio() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// `--escalate radio-cycle`: turn every Wi-Fi radio off, then on again
+pub async fn cycle_wifi_radio() -> anyhow::Result<()> {
+    if set_wifi_radios(RadioState::Off)? == 0 {
+        anyhow::bail!("No Wi-Fi radio found");
+    }
+    // WinRT objects are not Send: none is held across the await, the radios are looked up again
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+    set_wifi_radios(RadioState::On)?;
+    Ok(())
+}
+
+/// Switch every Wi-Fi radio to `state`; returns how many there are
+fn set_wifi_radios(state: RadioState) -> anyhow::Result<u32> {
+    let radios = get_radios()?;
+    let mut count = 0;
+    for i in 0..radios.Size()? {
+        let radio = radios.GetAt(i)?;
+        if radio.Kind()? != RadioKind::WiFi {
+            continue;
+        }
+        let name = radio.Name().unwrap_or_default();
+        let to = if state == RadioState::On { "on" } else { "off" };
+        tracing::info!("Turning Wi-Fi radio \"{}\" {}", name, to);
+        radio.SetStateAsync(state)?.get()?;
+        count += 1;
+    }
+    Ok(count)
+}
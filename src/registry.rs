@@ -0,0 +1,71 @@
+//! Registry reads (HKLM values used by policy and NCSI checks)
+
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::ERROR_SUCCESS;
+use windows::Win32::System::Registry::{
+    RegGetValueW, HKEY_LOCAL_MACHINE, RRF_RT_REG_DWORD, RRF_RT_REG_SZ,
+};
+
+/// NUL-terminated UTF-16 for Win32 string parameters
+fn wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
+/// Read a REG_DWORD under HKLM; None if the key or value is missing
+pub fn read_hklm_dword(subkey: &str, value: &str) -> Option<u32> {
+    let subkey = wide(subkey);
+    let value = wide(value);
+    let mut data = 0u32;
+    let mut size = std::mem::size_of::<u32>() as u32;
+    let status = unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            PCWSTR::from_raw(subkey.as_ptr()),
+            PCWSTR::from_raw(value.as_ptr()),
+            RRF_RT_REG_DWORD,
+            None,
+            Some(std::ptr::addr_of_mut!(data).cast()),
+            Some(&mut size),
+        )
+    };
+    (status == ERROR_SUCCESS).then_some(data)
+}
+
+/// Read a REG_SZ under HKLM; None if the key or value is missing
+pub fn read_hklm_string(subkey: &str, value: &str) -> Option<String> {
+    let subkey = wide(subkey);
+    let value = wide(value);
+    let mut size = 0u32;
+    let status = unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            PCWSTR::from_raw(subkey.as_ptr()),
+            PCWSTR::from_raw(value.as_ptr()),
+            RRF_RT_REG_SZ,
+            None,
+            None,
+            Some(&mut size),
+        )
+    };
+    if status != ERROR_SUCCESS {
+        return None;
+    }
+    // size is in bytes and includes the terminating NUL
+    let mut data = vec![0u16; (size as usize).div_ceil(2)];
+    let status = unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            PCWSTR::from_raw(subkey.as_ptr()),
+            PCWSTR::from_raw(value.as_ptr()),
+            RRF_RT_REG_SZ,
+            None,
+            Some(data.as_mut_ptr().cast()),
+            Some(&mut size),
+        )
+    };
+    if status != ERROR_SUCCESS {
+        return None;
+    }
+    let len = data.iter().position(|&c| c == 0).unwrap_or(data.len());
+    Some(String::from_utf16_lossy(&data[..len]))
+}
@@ -0,0 +1,50 @@
+//! Routing checks (which interface carries the IPv4 default route)
+
+use std::net::Ipv4Addr;
+use windows::Win32::Foundation::NO_ERROR;
+
+use windows::Win32::NetworkManagement::IpHelper::{
+    ConvertInterfaceGuidToLuid, ConvertInterfaceLuidToIndex, GetBestInterface, GetBestRoute,
+    MIB_IPFORWARDROW,
+};
+use windows::Win32::NetworkManagement::Ndis::NET_LUID_LH;
+
+/// Interface index of the adapter with this GUID (e.g. a WLAN interface GUID)
+fn interface_index(guid: &windows::core::GUID) -> Option<u32> {
+    let mut luid = NET_LUID_LH::default();
+    let mut index = 0u32;
+    unsafe {
+        if ConvertInterfaceGuidToLuid(guid, &mut luid) != NO_ERROR {
+            return None;
+        }
+        if ConvertInterfaceLuidToIndex(&luid, &mut index) != NO_ERROR {
+            return None;
+        }
+    }
+    Some(index)
+}
+
+/// Interface index the best 0.0.0.0/0 route egresses through; None if there is no default route
+fn default_route_interface() -> Option<u32> {
+    let mut row = MIB_IPFORWARDROW::default();
+    let status = unsafe { GetBestRoute(0, 0, &mut row) };
+    (status == NO_ERROR.0).then_some(row.dwForwardIfIndex)
+}
+
+/// Whether the IPv4 default route goes out through the interface with this GUID.
+/// None when either side cannot be determined.
+pub fn default_route_via(guid: &windows::core::GUID) -> Option<bool> {
+    Some(default_route_interface()? == interface_index(guid)?)
+}
+
+/// Whether traffic to `dest` leaves through the interface with this GUID (GetBestInterface).
+/// None when either side cannot be determined.
+pub fn egress_via(guid: &windows::core::GUID, dest: Ipv4Addr) -> Option<bool> {
+    let mut index = 0u32;
+    // IPAddr is in network byte order
+    let status = unsafe { GetBestInterface(u32::from_ne_bytes(dest.octets()), &mut index) };
+    if status != NO_ERROR.0 {
+        return None;
+    }
+    Some(index == interface_index(guid)?)
+}
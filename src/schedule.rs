@@ -0,0 +1,77 @@
+//! Active-hours window (`--active-hours 08:00-22:00`): recovery only runs inside it
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::Timelike;
+
+/// Daily local-time window, minutes since midnight; `end < start` wraps past midnight
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ActiveHours {
+    start: u32,
+    end: u32,
+}
+
+/// Parse "HH:MM" into minutes since midnight
+fn parse_hhmm(s: &str) -> Result<u32, String> {
+    let (h, m) = s
+        .trim()
+        .split_once(':')
+        .ok_or_else(|| format!("expected HH:MM, got \"{}\"", s))?;
+    let h: u32 = h
+        .parse()
+        .map_err(|_| format!("invalid hour in \"{}\"", s))?;
+    let m: u32 = m
+        .parse()
+        .map_err(|_| format!("invalid minute in \"{}\"", s))?;
+    if h > 23 || m > 59 {
+        return Err(format!("time out of range: \"{}\"", s));
+    }
+    Ok(h * 60 + m)
+}
+
+impl FromStr for ActiveHours {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| format!("expected HH:MM-HH:MM, got \"{}\"", s))?;
+        let start = parse_hhmm(start)?;
+        let end = parse_hhmm(end)?;
+        if start == end {
+            return Err("start and end must differ".to_string());
+        }
+        Ok(Self { start, end })
+    }
+}
+
+impl fmt::Display for ActiveHours {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}-{:02}:{:02}",
+            self.start / 60,
+            self.start % 60,
+            self.end / 60,
+            self.end % 60
+        )
+    }
+}
+
+impl ActiveHours {
+    /// Whether `minute` (since midnight) falls in the window; start inclusive, end exclusive
+    pub fn contains(&self, minute: u32) -> bool {
+        if self.start < self.end {
+            (self.start..self.end).contains(&minute)
+        } else {
+            minute >= self.start || minute < self.end
+        }
+    }
+
+    /// Whether the current local time falls in the window
+    pub fn contains_now(&self) -> bool {
+        let now = chrono::Local::now();
+        self.contains(now.hour() * 60 + now.minute())
+    }
+}
@@ -0,0 +1,220 @@
+//! State file written by the watch loop, and the one-line `--status` view of it
+
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::network::{ProbeFailure, RttStats, RttTrend};
+use crate::tray::Tray;
+use crate::types::{RecoveryEvent, StatusSnapshot, SCHEMA_VERSION};
+use crate::wlan::ConnectedNetwork;
+
+/// Connectivity state as seen by the loop
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum NetState {
+    Up,
+    Down,
+    Recovering,
+}
+
+/// Default state file: %ProgramData%\network-watchdog\state.json (temp dir if unset)
+pub fn default_path() -> PathBuf {
+    std::env::var_os("ProgramData")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join("network-watchdog")
+        .join("state.json")
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Writer for the loop's state file; keeps `since` across writes of the same state
+pub struct StateFile {
+    path: PathBuf,
+    interval: u64,
+    last: Option<StatusSnapshot>,
+    tray: Option<Tray>,
+    rtt: Option<RttStats>,
+    probe_error: Option<ProbeFailure>,
+    last_recovery: Option<RecoveryEvent>,
+}
+
+impl StateFile {
+    pub fn new(path: PathBuf, interval: u64) -> Self {
+        Self {
+            path,
+            interval,
+            last: None,
+            tray: None,
+            rtt: None,
+            probe_error: None,
+            last_recovery: None,
+        }
+    }
+
+    /// Mirror every state update to this tray icon (`--tray`)
+    pub fn with_tray(mut self, tray: Option<Tray>) -> Self {
+        self.tray = tray;
+        self
+    }
+
+    /// Probe round-trip stats written with the next updates
+    pub fn set_rtt(&mut self, rtt: Option<RttStats>) {
+        self.rtt = rtt;
+    }
+
+    /// Failure category of the last probe (None when it passed), written with the next updates
+    pub fn set_probe_error(&mut self, probe_error: Option<ProbeFailure>) {
+        self.probe_error = probe_error;
+    }
+
+    /// Outcome of the latest recovery round, written with the next updates
+    pub fn set_last_recovery(&mut self, event: RecoveryEvent) {
+        self.last_recovery = Some(event);
+    }
+
+    /// Record the current state (and connection, if known) and rewrite the file
+    pub fn update(&mut self, state: NetState, conn: Option<&ConnectedNetwork>) {
+        let now = unix_now();
+        let since = match self.last {
+            Some(ref last) if last.state == state => last.since,
+            _ => now,
+        };
+        let snapshot = StatusSnapshot {
+            schema_version: SCHEMA_VERSION,
+            state,
+            since,
+            updated: now,
+            interval: self.interval,
+            // Ethernet connections (`--ethernet-aware`) have no SSID or signal
+            ssid: conn.map(|c| c.ssid.clone()).filter(|s| !s.is_empty()),
+            profile: conn.map(|c| c.profile.clone()),
+            signal: conn
+                .filter(|c| !c.ssid.is_empty())
+                .map(|c| c.signal_quality),
+            ip: conn.and_then(|c| c.ip.clone()),
+            rtt: self.rtt.clone(),
+            probe_error: self.probe_error,
+            last_recovery: self.last_recovery.clone(),
+        };
+        if let Some(ref tray) = self.tray {
+            tray.set_state(state, snapshot.ssid.as_deref());
+        }
+        if let Err(e) = write_snapshot(&self.path, &snapshot) {
+            tracing::debug!("Failed to write state file {}: {}", self.path.display(), e);
+        }
+        self.last = Some(snapshot);
+    }
+}
+
+/// `--heartbeat-file`: write the current Unix time (s) so an external supervisor can tell
+/// from the content or mtime that the loop is still turning. Failures are logged, never fatal.
+pub fn touch_heartbeat(path: &Path) {
+    let result = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| std::fs::write(path, format!("{}\n", unix_now())));
+    if let Err(e) = result {
+        tracing::debug!("Failed to write heartbeat file {}: {}", path.display(), e);
+    }
+}
+
+/// Write via a temp file + rename so `--status` never reads a half-written file
+fn write_snapshot(path: &Path, snapshot: &StatusSnapshot) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_string_pretty(snapshot)?)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Compact duration: 45s, 12m, 3h12m, 2d3h
+fn format_age(secs: u64) -> String {
+    match secs {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m", s / 60),
+        s if s < 86400 => format!("{}h{}m", s / 3600, s % 3600 / 60),
+        s => format!("{}d{}h", s / 86400, s % 86400 / 3600),
+    }
+}
+
+/// One-line status, e.g. `UP ssid=Home signal=82% ip=192.168.1.20/24 rtt=35ms p95=80ms trend=steady since=12m`, `DOWN recovering probe_error=timeout since=3m`,
+/// or `STALE ...` when the loop has not written the file for three intervals
+pub fn status_line(snapshot: &StatusSnapshot, now: u64) -> String {
+    let mut line = match snapshot.state {
+        NetState::Up => "UP".to_string(),
+        NetState::Down => "DOWN".to_string(),
+        NetState::Recovering => "DOWN recovering".to_string(),
+    };
+    if let Some(ref ssid) = snapshot.ssid {
+        line.push_str(&format!(" ssid={}", ssid));
+    }
+    if let Some(signal) = snapshot.signal {
+        line.push_str(&format!(" signal={}%", signal));
+    }
+    // Prefer an IPv4 address; IPv6 lists usually start with the link-local one
+    let addr = snapshot.ip.as_ref().and_then(|ip| {
+        ip.addresses
+            .iter()
+            .find(|a| !a.contains(':'))
+            .or(ip.addresses.first())
+    });
+    if let Some(addr) = addr {
+        line.push_str(&format!(" ip={}", addr));
+    }
+    if let Some(ref rtt) = snapshot.rtt {
+        let trend = match rtt.trend {
+            RttTrend::Improving => "improving",
+            RttTrend::Steady => "steady",
+            RttTrend::Worsening => "worsening",
+        };
+        line.push_str(&format!(
+            " rtt={}ms p95={}ms trend={}",
+            rtt.avg_ms, rtt.p95_ms, trend
+        ));
+    }
+    if let Some(kind) = snapshot.probe_error {
+        line.push_str(&format!(" probe_error={}", kind));
+    }
+    line.push_str(&format!(
+        " since={}",
+        format_age(now.saturating_sub(snapshot.since))
+    ));
+    let stale_after = snapshot.interval.saturating_mul(3).max(60);
+    if now.saturating_sub(snapshot.updated) > stale_after {
+        line = format!(
+            "STALE {} (updated {} ago)",
+            line,
+            format_age(now.saturating_sub(snapshot.updated))
+        );
+    }
+    line
+}
+
+/// `--status`: print the state file as one line (or JSON) and return
+pub fn print_status(path: &Path, json: bool) -> anyhow::Result<()> {
+    let text = std::fs::read_to_string(path).map_err(|e| {
+        anyhow::anyhow!(
+            "No state file at {} ({}); is the watchdog loop running?",
+            path.display(),
+            e
+        )
+    })?;
+    let snapshot: StatusSnapshot = serde_json::from_str(&text)?;
+    if json {
+        println!("{}", serde_json::to_string(&snapshot)?);
+    } else {
+        println!("{}", status_line(&snapshot, unix_now()));
+    }
+    Ok(())
+}
@@ -0,0 +1,92 @@
+//! Per-profile connection statistics, optionally persisted as JSON (`--stats-file`)
+
+use std::path::Path;
+
+pub use crate::types::Stats;
+use crate::types::SCHEMA_VERSION;
+use crate::wlan::RecoveryReport;
+
+impl Stats {
+    /// Load from `path`; a missing or unreadable file starts fresh. Older files are upgraded
+    /// in memory and rewritten with the current `schema_version` on the next save.
+    pub fn load(path: &Path) -> Self {
+        let mut stats = match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|e| {
+                tracing::warn!(
+                    "Stats file {} is invalid ({}), starting fresh",
+                    path.display(),
+                    e
+                );
+                Self::default()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => {
+                tracing::warn!("Failed to read stats file {}: {}", path.display(), e);
+                Self::default()
+            }
+        };
+        stats.schema_version = SCHEMA_VERSION;
+        stats
+    }
+
+    /// Write to `path` as pretty JSON; failures are logged, not fatal
+    pub fn save(&self, path: &Path) {
+        let result = serde_json::to_string_pretty(self)
+            .map_err(anyhow::Error::from)
+            .and_then(|json| std::fs::write(path, json).map_err(anyhow::Error::from));
+        if let Err(e) = result {
+            tracing::warn!("Failed to write stats file {}: {}", path.display(), e);
+        }
+    }
+
+    /// Add the attempts of one recovery round
+    pub fn record(&mut self, report: &RecoveryReport) {
+        for attempt in &report.attempts {
+            let entry = self.profiles.entry(attempt.profile.clone()).or_default();
+            entry.attempts += 1;
+            if attempt.restored {
+                entry.successes += 1;
+                entry.last_success =
+                    Some(chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string());
+                self.last_good = Some(attempt.profile.clone());
+            }
+        }
+    }
+
+    /// Log a table: profile, attempts, successes, success rate, last success
+    pub fn log_table(&self) {
+        if self.profiles.is_empty() {
+            return;
+        }
+        let width = self
+            .profiles
+            .keys()
+            .map(|p| p.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max("Profile".len());
+        tracing::info!("Profile statistics:");
+        tracing::info!(
+            "  {:<width$}  {:>8}  {:>9}  {:>5}  Last success",
+            "Profile",
+            "Attempts",
+            "Successes",
+            "Rate",
+        );
+        for (profile, s) in &self.profiles {
+            let rate = if s.attempts == 0 {
+                0.0
+            } else {
+                100.0 * s.successes as f64 / s.attempts as f64
+            };
+            tracing::info!(
+                "  {:<width$}  {:>8}  {:>9}  {:>4.0}%  {}",
+                profile,
+                s.attempts,
+                s.successes,
+                rate,
+                s.last_success.as_deref().unwrap_or("never"),
+            );
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! `--install-task` / `--uninstall-task`: run the watchdog from a Windows Scheduled Task
+
+use std::env;
+use std::process::Command;
+
+use crate::admin;
+
+/// Name of the scheduled task (in the Task Scheduler root folder)
+pub const TASK_NAME: &str = "Network Watchdog";
+
+/// When the scheduled task starts the watchdog
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum TaskTrigger {
+    /// When the current user logs on, in their session with highest privileges
+    #[default]
+    Logon,
+    /// At system startup as LocalSystem, before anyone logs on (no console)
+    Boot,
+}
+
+/// Arguments that configure the task itself and are not forwarded to it
+const OWN_FLAGS: &[&str] = &["--install-task", "--elevated-child"];
+
+/// `args` (this run's, without the program name) minus the task-management flags (and
+/// `--task-trigger <VALUE>`)
+pub(crate) fn forwarded_args(args: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut forwarded = Vec::new();
+    let mut iter = args.into_iter();
+    while let Some(arg) = iter.next() {
+        if OWN_FLAGS.contains(&arg.as_str()) || arg.starts_with("--task-trigger=") {
+            continue;
+        }
+        if arg == "--task-trigger" {
+            iter.next();
+            continue;
+        }
+        forwarded.push(arg);
+    }
+    forwarded
+}
+
+/// Escape text for an XML element body
+pub(crate) fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Task Scheduler XML: the trigger, highest privileges, no execution time limit, restart on
+/// failure, and the exe with `arguments` (already quoted for CommandLineToArgvW), started in
+/// `working_dir` so relative paths among the arguments resolve as they did at install time
+pub(crate) fn task_xml(
+    trigger: TaskTrigger,
+    exe: &str,
+    arguments: &str,
+    working_dir: &str,
+) -> String {
+    let (trigger_xml, principal) = match trigger {
+        TaskTrigger::Logon => (
+            "<LogonTrigger><Enabled>true</Enabled></LogonTrigger>",
+            "<LogonType>InteractiveToken</LogonType><RunLevel>HighestAvailable</RunLevel>",
+        ),
+        TaskTrigger::Boot => (
+            "<BootTrigger><Enabled>true</Enabled></BootTrigger>",
+            "<UserId>S-1-5-18</UserId><RunLevel>HighestAvailable</RunLevel>",
+        ),
+    };
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-16"?>
+<Task version="1.2" xmlns="http://schemas.microsoft.com/windows/2004/02/mit/task">
+  <RegistrationInfo><Description>Probe connectivity and recover Wi-Fi when it drops</Description></RegistrationInfo>
+  <Triggers>{}</Triggers>
+  <Principals><Principal id="Author">{}</Principal></Principals>
+  <Settings>
+    <MultipleInstancesPolicy>IgnoreNew</MultipleInstancesPolicy>
+    <DisallowStartIfOnBatteries>false</DisallowStartIfOnBatteries>
+    <StopIfGoingOnBatteries>false</StopIfGoingOnBatteries>
+    <ExecutionTimeLimit>PT0S</ExecutionTimeLimit>
+    <RestartOnFailure><Interval>PT1M</Interval><Count>3</Count></RestartOnFailure>
+  </Settings>
+  <Actions Context="Author">
+    <Exec><Command>{}</Command><Arguments>{}</Arguments><WorkingDirectory>{}</WorkingDirectory></Exec>
+  </Actions>
+</Task>
+"#,
+        trigger_xml,
+        principal,
+        xml_escape(exe),
+        xml_escape(arguments),
+        xml_escape(working_dir)
+    )
+}
+
+/// Run schtasks with `args`; its output on failure
+fn schtasks(args: &[&str]) -> anyhow::Result<String> {
+    let out = Command::new("schtasks")
+        .args(args)
+        .output()
+        .map_err(|e| anyhow::anyhow!("Failed to run schtasks: {}", e))?;
+    let stdout = String::from_utf8_lossy(&out.stdout).into_owned();
+    if !out.status.success() {
+        anyhow::bail!(
+            "schtasks failed: {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        );
+    }
+    Ok(stdout)
+}
+
+/// Create (or replace) the scheduled task running this exe with the current arguments. The
+/// definition goes through an XML file: schtasks /TR is limited to 261 characters.
+pub fn install(trigger: TaskTrigger) -> anyhow::Result<()> {
+    let exe =
+        env::current_exe().map_err(|e| anyhow::anyhow!("Failed to get current exe path: {}", e))?;
+    let exe = exe.to_string_lossy().into_owned();
+    // Without it the task starts in C:\Windows\System32
+    let working_dir = env::current_dir()
+        .map_err(|e| anyhow::anyhow!("Failed to get current directory: {}", e))?;
+    let working_dir = working_dir.to_string_lossy().into_owned();
+    let args = forwarded_args(env::args().skip(1));
+    // The task definition is readable by administrators and backup tools: keep passwords out
+    if let Some((flag, var)) = args.iter().find_map(|a| admin::secret_flag(a)) {
+        anyhow::bail!(
+            "Refusing to store {} in the scheduled task in plain text; drop it and set the {} \
+             environment variable for the account the task runs as (SYSTEM with \
+             --task-trigger boot) instead",
+            flag,
+            var
+        );
+    }
+    let arguments = args
+        .iter()
+        .map(|a| admin::quote_arg(a))
+        .collect::<Vec<_>>()
+        .join(" ");
+    tracing::info!(
+        "Scheduled task command: {} {} (in {})",
+        exe,
+        arguments,
+        working_dir
+    );
+
+    // schtasks expects the file in the encoding the declaration names: UTF-16LE with BOM
+    let xml = task_xml(trigger, &exe, &arguments, &working_dir);
+    let bytes: Vec<u8> = std::iter::once(0xfeffu16)
+        .chain(xml.encode_utf16())
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    let path = env::temp_dir().join(format!("network-watchdog-task-{}.xml", std::process::id()));
+    std::fs::write(&path, bytes)
+        .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))?;
+    let created = schtasks(&[
+        "/Create",
+        "/TN",
+        TASK_NAME,
+        "/XML",
+        &path.to_string_lossy(),
+        "/F",
+    ]);
+    let _ = std::fs::remove_file(&path);
+    created?;
+
+    // Read the definition back: the arguments must have survived the round-trip unchanged
+    let stored = schtasks(&["/Query", "/TN", TASK_NAME, "/XML"])?;
+    if !arguments.is_empty() && !stored.contains(&xml_escape(&arguments)) {
+        tracing::warn!(
+            "Scheduled task arguments differ from what was written; check them with \
+             schtasks /Query /TN \"{}\" /XML",
+            TASK_NAME
+        );
+    }
+    tracing::info!(
+        "Scheduled task \"{}\" installed (runs at {})",
+        TASK_NAME,
+        match trigger {
+            TaskTrigger::Logon => "logon",
+            TaskTrigger::Boot => "boot",
+        }
+    );
+    Ok(())
+}
+
+/// Delete the scheduled task created by `install`
+pub fn uninstall() -> anyhow::Result<()> {
+    schtasks(&["/Delete", "/TN", TASK_NAME, "/F"])?;
+    tracing::info!("Scheduled task \"{}\" removed", TASK_NAME);
+    Ok(())
+}
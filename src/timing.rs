@@ -0,0 +1,252 @@
+//! Loop timing: how long to sleep before the next check, as a pure decision over the
+//! iteration's outcome so every sleep in the loop goes through one place
+
+use std::time::Duration;
+
+/// Sleep after a round that found no Wi-Fi hardware (nothing to retry until it is plugged in)
+pub const NO_HARDWARE_BACKOFF_SECS: u64 = 600;
+
+/// Default cap for the failed-recovery backoff (`--backoff-max`)
+pub const DEFAULT_BACKOFF_MAX_SECS: u64 = 3600;
+
+/// How a loop iteration ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoopState {
+    /// Probe passed (or the window still trusts the network)
+    Up,
+    /// Checking paused from the tray
+    Paused,
+    /// Down, but no recovery ran this iteration (window threshold, active hours, power
+    /// source, --monitor-only)
+    Down,
+    /// A recovery round restored the network
+    Recovered,
+    /// A recovery round ran and failed; `failures` counts the failed rounds in a row (from 1)
+    RecoveryFailed { failures: u32 },
+    /// A recovery round found no Wi-Fi hardware at all
+    NoHardware,
+}
+
+/// Failed recovery rounds in a row, for the backoff; reset once the network is up again
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FailureStreak(u32);
+
+impl FailureStreak {
+    /// Count one more failed round and return the state to sleep on
+    pub fn failed(&mut self) -> LoopState {
+        self.0 = self.0.saturating_add(1);
+        LoopState::RecoveryFailed { failures: self.0 }
+    }
+
+    /// The network is up (probe passed or a round restored it): the next failure starts over
+    pub fn reset(&mut self) {
+        self.0 = 0;
+    }
+}
+
+/// Timing settings from the command line
+#[derive(Clone, Copy, Debug)]
+pub struct TimingConfig {
+    /// `--interval`
+    pub interval: Duration,
+    /// `--down-interval`: sleep while down (defaults to `interval`)
+    pub down_interval: Duration,
+    /// `--backoff-factor`: each further failed round in a row multiplies the down interval by
+    /// this (1: no backoff)
+    pub backoff_factor: u32,
+    /// `--backoff-max`: the backoff never sleeps longer than this
+    pub backoff_max: Duration,
+    /// `--jitter-ms`: up to this much random time added to every sleep
+    pub jitter: Duration,
+    /// `--cooldown`: minimum sleep after a round restored the network, to let it settle
+    pub cooldown: Duration,
+    /// Minimum sleep after `LoopState::NoHardware`
+    pub no_hardware_backoff: Duration,
+}
+
+impl TimingConfig {
+    /// Plain `--interval` timing: no backoff, jitter or cooldown
+    pub fn new(interval_secs: u64) -> Self {
+        Self {
+            interval: Duration::from_secs(interval_secs),
+            down_interval: Duration::from_secs(interval_secs),
+            backoff_factor: 1,
+            backoff_max: Duration::from_secs(DEFAULT_BACKOFF_MAX_SECS),
+            jitter: Duration::ZERO,
+            cooldown: Duration::ZERO,
+            no_hardware_backoff: Duration::from_secs(NO_HARDWARE_BACKOFF_SECS),
+        }
+    }
+
+    /// `next_sleep` with a fresh jitter roll, for the loop
+    pub fn sleep_for(&self, state: &LoopState) -> Duration {
+        next_sleep(state, self, roll())
+    }
+}
+
+/// Pseudo-random value in [0, 1), seeded from the clock (good enough to spread sleeps)
+fn roll() -> f64 {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    f64::from(nanos) / 1e9
+}
+
+/// How long to sleep before the next check: the interval while up, the down interval while
+/// down, growing by the backoff factor per failed round in a row (capped, never below the down
+/// interval), at least the cooldown after a restore and the no-hardware backoff when there is
+/// no Wi-Fi adapter. `jitter_roll` in [0, 1) picks how much of `cfg.jitter` is added; it is a
+/// parameter so the decision stays pure.
+pub fn next_sleep(state: &LoopState, cfg: &TimingConfig, jitter_roll: f64) -> Duration {
+    let base = match *state {
+        LoopState::Up | LoopState::Paused => cfg.interval,
+        LoopState::Down => cfg.down_interval,
+        LoopState::Recovered => cfg.interval.max(cfg.cooldown),
+        LoopState::RecoveryFailed { failures } => {
+            let exponent = failures.saturating_sub(1).min(i32::MAX as u32) as i32;
+            let factor = f64::from(cfg.backoff_factor.max(1)).powi(exponent);
+            let grown = cfg.down_interval.as_secs_f64() * factor;
+            let capped = grown.min(cfg.backoff_max.as_secs_f64());
+            Duration::from_secs_f64(capped).max(cfg.down_interval)
+        }
+        LoopState::NoHardware => cfg.interval.max(cfg.no_hardware_backoff),
+    };
+    base + cfg.jitter.mul_f64(jitter_roll.clamp(0.0, 1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(s: u64) -> Duration {
+        Duration::from_secs(s)
+    }
+
+    fn config() -> TimingConfig {
+        TimingConfig {
+            down_interval: secs(20),
+            backoff_factor: 2,
+            backoff_max: secs(300),
+            cooldown: secs(120),
+            ..TimingConfig::new(60)
+        }
+    }
+
+    #[test]
+    fn healthy_sleeps_the_interval() {
+        assert_eq!(next_sleep(&LoopState::Up, &config(), 0.0), secs(60));
+        assert_eq!(next_sleep(&LoopState::Paused, &config(), 0.0), secs(60));
+    }
+
+    #[test]
+    fn down_without_recovery_sleeps_the_down_interval() {
+        assert_eq!(next_sleep(&LoopState::Down, &config(), 0.0), secs(20));
+    }
+
+    #[test]
+    fn just_recovered_waits_out_the_cooldown() {
+        assert_eq!(next_sleep(&LoopState::Recovered, &config(), 0.0), secs(120));
+        let short = TimingConfig {
+            cooldown: secs(10),
+            ..config()
+        };
+        assert_eq!(next_sleep(&LoopState::Recovered, &short, 0.0), secs(60));
+    }
+
+    #[test]
+    fn backoff_grows_across_repeated_failures_up_to_the_cap() {
+        let cfg = config();
+        let mut streak = FailureStreak::default();
+        let sleeps: Vec<Duration> = (0..6)
+            .map(|_| next_sleep(&streak.failed(), &cfg, 0.0))
+            .collect();
+        assert_eq!(
+            sleeps,
+            [
+                secs(20),
+                secs(40),
+                secs(80),
+                secs(160),
+                secs(300),
+                secs(300)
+            ]
+        );
+    }
+
+    #[test]
+    fn backoff_resets_once_the_network_is_up() {
+        let cfg = config();
+        let mut streak = FailureStreak::default();
+        for _ in 0..4 {
+            streak.failed();
+        }
+        assert_eq!(next_sleep(&streak.failed(), &cfg, 0.0), secs(300));
+        streak.reset();
+        assert_eq!(next_sleep(&streak.failed(), &cfg, 0.0), secs(20));
+    }
+
+    #[test]
+    fn factor_one_never_backs_off() {
+        let cfg = TimingConfig {
+            backoff_factor: 1,
+            ..config()
+        };
+        let state = LoopState::RecoveryFailed { failures: 10 };
+        assert_eq!(next_sleep(&state, &cfg, 0.0), secs(20));
+    }
+
+    #[test]
+    fn backoff_cap_below_down_interval_keeps_the_down_interval() {
+        let cfg = TimingConfig {
+            backoff_max: secs(5),
+            ..config()
+        };
+        let state = LoopState::RecoveryFailed { failures: 3 };
+        assert_eq!(next_sleep(&state, &cfg, 0.0), secs(20));
+    }
+
+    #[test]
+    fn no_hardware_sleeps_at_least_the_hardware_backoff() {
+        assert_eq!(
+            next_sleep(&LoopState::NoHardware, &config(), 0.0),
+            secs(NO_HARDWARE_BACKOFF_SECS)
+        );
+        let slow = TimingConfig::new(NO_HARDWARE_BACKOFF_SECS + 60);
+        assert_eq!(
+            next_sleep(&LoopState::NoHardware, &slow, 0.0),
+            secs(NO_HARDWARE_BACKOFF_SECS + 60)
+        );
+    }
+
+    #[test]
+    fn jitter_adds_the_rolled_share_to_every_state() {
+        let cfg = TimingConfig {
+            jitter: Duration::from_millis(1000),
+            ..config()
+        };
+        assert_eq!(next_sleep(&LoopState::Up, &cfg, 0.0), secs(60));
+        assert_eq!(
+            next_sleep(&LoopState::Up, &cfg, 0.5),
+            secs(60) + Duration::from_millis(500)
+        );
+        let failed = LoopState::RecoveryFailed { failures: 2 };
+        assert_eq!(
+            next_sleep(&failed, &cfg, 0.25),
+            secs(40) + Duration::from_millis(250)
+        );
+    }
+
+    #[test]
+    fn default_config_keeps_the_plain_interval() {
+        let cfg = TimingConfig::new(60);
+        for state in [
+            LoopState::Up,
+            LoopState::Down,
+            LoopState::Recovered,
+            LoopState::RecoveryFailed { failures: 5 },
+        ] {
+            assert_eq!(cfg.sleep_for(&state), secs(60), "{:?}", state);
+        }
+    }
+}
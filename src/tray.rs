@@ -0,0 +1,339 @@
+//! Notification-area icon (`--tray`): colored status dot plus a Check now / Pause / Quit menu.
+//! The icon lives on its own thread with a hidden window and message loop; menu picks reach
+//! the async loop over a channel.
+
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicIsize, Ordering};
+use std::sync::Mutex;
+
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use windows::core::{w, PCWSTR};
+use windows::Win32::Foundation::{HWND, LPARAM, LRESULT, POINT, TRUE, WPARAM};
+use windows::Win32::Graphics::Gdi::{CreateBitmap, DeleteObject};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::UI::Shell::{
+    Shell_NotifyIconW, NIF_ICON, NIF_MESSAGE, NIF_TIP, NIM_ADD, NIM_DELETE, NIM_MODIFY,
+    NOTIFYICONDATAW,
+};
+use windows::Win32::UI::WindowsAndMessaging::{
+    AppendMenuW, CreateIconIndirect, CreatePopupMenu, CreateWindowExW, DefWindowProcW, DestroyMenu,
+    DispatchMessageW, GetCursorPos, GetMessageW, PostMessageW, RegisterClassW, SetForegroundWindow,
+    TrackPopupMenu, TranslateMessage, HICON, ICONINFO, MF_CHECKED, MF_SEPARATOR, MF_STRING, MSG,
+    TPM_RETURNCMD, TPM_RIGHTBUTTON, WINDOW_EX_STYLE, WINDOW_STYLE, WM_APP, WM_CONTEXTMENU,
+    WM_LBUTTONDBLCLK, WM_RBUTTONUP, WNDCLASSW,
+};
+
+use crate::state::NetState;
+
+/// Menu picks sent to the watch loop
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrayCommand {
+    /// Probe (and recover) right away instead of waiting out the interval
+    CheckNow,
+    /// Pause (true) or resume (false) checking
+    Pause(bool),
+    Quit,
+}
+
+/// Callback message for icon clicks
+const WM_TRAY: u32 = WM_APP + 1;
+/// Posted by `Tray::set_state` to redraw the icon from `PENDING`
+const WM_REFRESH: u32 = WM_APP + 2;
+
+const MENU_CHECK_NOW: usize = 1;
+const MENU_PAUSE: usize = 2;
+const MENU_QUIT: usize = 3;
+
+/// Latest state and tooltip detail from the loop, picked up on WM_REFRESH
+static PENDING: Mutex<Option<(NetState, String)>> = Mutex::new(None);
+
+/// Window of the icon while it is shown (0: none), for `remove_icon`
+static ICON_HWND: AtomicIsize = AtomicIsize::new(0);
+
+/// Tray-thread state used by the window procedure
+struct TrayThread {
+    commands: UnboundedSender<TrayCommand>,
+    icons: [HICON; 3],
+    state: NetState,
+    detail: String,
+    paused: bool,
+}
+
+thread_local! {
+    static TRAY: RefCell<Option<TrayThread>> = const { RefCell::new(None) };
+}
+
+/// Handle for updating the icon from the loop
+pub struct Tray {
+    /// Hidden window of the tray thread (HWND as isize: HWND is not Send)
+    hwnd: isize,
+}
+
+impl Tray {
+    /// Create the icon on a new thread; returns the handle and the menu command stream
+    pub fn spawn() -> anyhow::Result<(Self, UnboundedReceiver<TrayCommand>)> {
+        let (commands, receiver) = unbounded_channel();
+        let (ready, created) = std::sync::mpsc::sync_channel(1);
+        std::thread::spawn(move || unsafe {
+            match create_window() {
+                Ok(hwnd) => {
+                    TRAY.with(|t| {
+                        *t.borrow_mut() = Some(TrayThread {
+                            commands,
+                            icons: [
+                                status_icon(0x2e, 0xa0, 0x43),
+                                status_icon(0xd3, 0x2f, 0x2f),
+                                status_icon(0xf2, 0xb7, 0x05),
+                            ],
+                            state: NetState::Up,
+                            detail: "starting".to_string(),
+                            paused: false,
+                        })
+                    });
+                    notify_icon(hwnd, NIM_ADD);
+                    ICON_HWND.store(hwnd.0, Ordering::SeqCst);
+                    let _ = ready.send(Ok(hwnd.0));
+                    let mut msg = MSG::default();
+                    while GetMessageW(&mut msg, None, 0, 0).as_bool() {
+                        let _ = TranslateMessage(&msg);
+                        DispatchMessageW(&msg);
+                    }
+                }
+                Err(e) => {
+                    let _ = ready.send(Err(e));
+                }
+            }
+        });
+        let hwnd = created
+            .recv()
+            .map_err(|_| anyhow::anyhow!("Tray thread exited during setup"))??;
+        Ok((Self { hwnd }, receiver))
+    }
+
+    /// Show `state` (green up, red down, yellow recovering) with `detail` in the tooltip
+    pub fn set_state(&self, state: NetState, detail: Option<&str>) {
+        *PENDING.lock().unwrap() = Some((state, detail.unwrap_or_default().to_string()));
+        unsafe {
+            let _ = PostMessageW(HWND(self.hwnd), WM_REFRESH, WPARAM(0), LPARAM(0));
+        }
+    }
+}
+
+impl Drop for Tray {
+    fn drop(&mut self) {
+        remove_icon();
+    }
+}
+
+/// Take the icon out of the notification area, so no ghost icon stays behind until the mouse
+/// passes over it. Runs on `Tray` drop and before `std::process::exit`, which skips drops; a
+/// no-op once removed or when there is no tray.
+pub fn remove_icon() {
+    let hwnd = ICON_HWND.swap(0, Ordering::SeqCst);
+    if hwnd == 0 {
+        return;
+    }
+    // The icon is identified by window and ID, so any thread may delete it
+    let data = NOTIFYICONDATAW {
+        cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
+        hWnd: HWND(hwnd),
+        uID: 1,
+        ..Default::default()
+    };
+    unsafe {
+        let _ = Shell_NotifyIconW(NIM_DELETE, &data);
+    }
+}
+
+unsafe fn create_window() -> anyhow::Result<HWND> {
+    let instance = GetModuleHandleW(None)?;
+    let class = WNDCLASSW {
+        lpfnWndProc: Some(window_proc),
+        hInstance: instance.into(),
+        lpszClassName: w!("NetworkWatchdogTray"),
+        ..Default::default()
+    };
+    if RegisterClassW(&class) == 0 {
+        anyhow::bail!(
+            "RegisterClassW failed: {}",
+            windows::core::Error::from_win32()
+        );
+    }
+    // Never shown: it only receives the icon's callback messages
+    let hwnd = CreateWindowExW(
+        WINDOW_EX_STYLE(0),
+        w!("NetworkWatchdogTray"),
+        w!("Network Watchdog"),
+        WINDOW_STYLE(0),
+        0,
+        0,
+        0,
+        0,
+        None,
+        None,
+        instance,
+        None,
+    );
+    if hwnd.0 == 0 {
+        anyhow::bail!(
+            "CreateWindowExW failed: {}",
+            windows::core::Error::from_win32()
+        );
+    }
+    Ok(hwnd)
+}
+
+/// 16x16 filled circle in the given color (transparent corners via the alpha channel)
+unsafe fn status_icon(r: u8, g: u8, b: u8) -> HICON {
+    const SIZE: i32 = 16;
+    let mut pixels = Vec::with_capacity((SIZE * SIZE) as usize);
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            let (dx, dy) = (x * 2 + 1 - SIZE, y * 2 + 1 - SIZE);
+            let inside = dx * dx + dy * dy <= (SIZE - 2) * (SIZE - 2);
+            // BGRA
+            pixels.push(if inside {
+                u32::from_le_bytes([b, g, r, 0xff])
+            } else {
+                0
+            });
+        }
+    }
+    let mask = vec![0u8; (SIZE * SIZE / 8) as usize];
+    let color = CreateBitmap(SIZE, SIZE, 1, 32, Some(pixels.as_ptr().cast()));
+    let mask = CreateBitmap(SIZE, SIZE, 1, 1, Some(mask.as_ptr().cast()));
+    let info = ICONINFO {
+        fIcon: TRUE,
+        xHotspot: 0,
+        yHotspot: 0,
+        hbmMask: mask,
+        hbmColor: color,
+    };
+    let icon = CreateIconIndirect(&info).unwrap_or_default();
+    DeleteObject(color);
+    DeleteObject(mask);
+    icon
+}
+
+/// Add, update or remove the icon from the tray-thread state
+unsafe fn notify_icon(hwnd: HWND, message: windows::Win32::UI::Shell::NOTIFY_ICON_MESSAGE) {
+    let mut data = NOTIFYICONDATAW {
+        cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
+        hWnd: hwnd,
+        uID: 1,
+        uFlags: NIF_ICON | NIF_MESSAGE | NIF_TIP,
+        uCallbackMessage: WM_TRAY,
+        ..Default::default()
+    };
+    TRAY.with(|t| {
+        if let Some(ref tray) = *t.borrow() {
+            data.hIcon = tray.icons[match tray.state {
+                NetState::Up => 0,
+                NetState::Down => 1,
+                NetState::Recovering => 2,
+            }];
+            let state = match tray.state {
+                NetState::Up => "up",
+                NetState::Down => "down",
+                NetState::Recovering => "recovering",
+            };
+            let mut tip = format!("Network Watchdog: {}", state);
+            if !tray.detail.is_empty() {
+                tip.push_str(&format!(" ({})", tray.detail));
+            }
+            if tray.paused {
+                tip.push_str(", paused");
+            }
+            let wide: Vec<u16> = tip.encode_utf16().take(data.szTip.len() - 1).collect();
+            data.szTip[..wide.len()].copy_from_slice(&wide);
+        }
+    });
+    let _ = Shell_NotifyIconW(message, &data);
+}
+
+/// Pop up the menu at the cursor and act on the pick
+unsafe fn show_menu(hwnd: HWND) {
+    let Ok(menu) = CreatePopupMenu() else {
+        return;
+    };
+    let paused = TRAY.with(|t| t.borrow().as_ref().is_some_and(|t| t.paused));
+    let pause_flags = if paused {
+        MF_STRING | MF_CHECKED
+    } else {
+        MF_STRING
+    };
+    let _ = AppendMenuW(menu, MF_STRING, MENU_CHECK_NOW, w!("Check now"));
+    let _ = AppendMenuW(menu, pause_flags, MENU_PAUSE, w!("Pause"));
+    let _ = AppendMenuW(menu, MF_SEPARATOR, 0, PCWSTR::null());
+    let _ = AppendMenuW(menu, MF_STRING, MENU_QUIT, w!("Quit"));
+    let mut cursor = POINT::default();
+    let _ = GetCursorPos(&mut cursor);
+    // Without this the menu does not close when clicking elsewhere
+    let _ = SetForegroundWindow(hwnd);
+    let picked = TrackPopupMenu(
+        menu,
+        TPM_RETURNCMD | TPM_RIGHTBUTTON,
+        cursor.x,
+        cursor.y,
+        0,
+        hwnd,
+        None,
+    );
+    let _ = DestroyMenu(menu);
+    match picked.0 as usize {
+        MENU_CHECK_NOW => send(TrayCommand::CheckNow),
+        MENU_PAUSE => {
+            TRAY.with(|t| {
+                if let Some(ref mut tray) = *t.borrow_mut() {
+                    tray.paused = !paused;
+                }
+            });
+            notify_icon(hwnd, NIM_MODIFY);
+            send(TrayCommand::Pause(!paused));
+        }
+        MENU_QUIT => {
+            remove_icon();
+            send(TrayCommand::Quit);
+        }
+        _ => {}
+    }
+}
+
+fn send(command: TrayCommand) {
+    TRAY.with(|t| {
+        if let Some(ref tray) = *t.borrow() {
+            let _ = tray.commands.send(command);
+        }
+    });
+}
+
+unsafe extern "system" fn window_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    match msg {
+        WM_TRAY => {
+            match lparam.0 as u32 & 0xffff {
+                WM_RBUTTONUP | WM_CONTEXTMENU => show_menu(hwnd),
+                WM_LBUTTONDBLCLK => send(TrayCommand::CheckNow),
+                _ => {}
+            }
+            LRESULT(0)
+        }
+        WM_REFRESH => {
+            if let Some((state, detail)) = PENDING.lock().unwrap().take() {
+                TRAY.with(|t| {
+                    if let Some(ref mut tray) = *t.borrow_mut() {
+                        tray.state = state;
+                        tray.detail = detail;
+                    }
+                });
+                notify_icon(hwnd, NIM_MODIFY);
+            }
+            LRESULT(0)
+        }
+        _ => DefWindowProcW(hwnd, msg, wparam, lparam),
+    }
+}
@@ -0,0 +1,167 @@
+//! JSON shapes shared by every JSON output (state file, `--status --json`, `--stats-file`,
+//! `--list-bss`), versioned with `schema_version`; `--print-schema` dumps their JSON Schema for
+//! integrators
+
+use std::collections::BTreeMap;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::ipconfig::IpConfig;
+use crate::network::{ProbeFailure, RttStats};
+use crate::state::{self, NetState};
+use crate::wlan::{ConnectedNetwork, RecoveryReport};
+
+/// Version of the shapes below; bumped on any incompatible change (renamed or removed field,
+/// changed meaning). Adding an optional field does not bump it.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The watch loop's current view: the state file's contents and `--status --json`
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct StatusSnapshot {
+    /// `SCHEMA_VERSION` of the writer; 0 for files written before versioning
+    #[serde(default)]
+    pub schema_version: u32,
+    pub state: NetState,
+    /// Unix time (s) the current state began
+    pub since: u64,
+    /// Unix time (s) of the last write; the loop rewrites the file every iteration
+    pub updated: u64,
+    /// Loop check interval (s), used by `--status` to detect a stale file
+    pub interval: u64,
+    pub ssid: Option<String>,
+    pub profile: Option<String>,
+    /// Wi-Fi signal quality, 0-100
+    pub signal: Option<u32>,
+    #[serde(default)]
+    pub ip: Option<IpConfig>,
+    /// Recent probe round-trips
+    #[serde(default)]
+    pub rtt: Option<RttStats>,
+    /// Why the last probe failed, while it is failing
+    #[serde(default)]
+    pub probe_error: Option<ProbeFailure>,
+    /// The most recent recovery round
+    #[serde(default)]
+    pub last_recovery: Option<RecoveryEvent>,
+}
+
+/// Outcome of one recovery round
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct RecoveryEvent {
+    pub schema_version: u32,
+    /// Unix time (s) the round ended
+    pub finished: u64,
+    pub duration_ms: u64,
+    /// Whether the round restored the network
+    pub restored: bool,
+    /// Profile (or escalation step) that restored it
+    pub profile: Option<String>,
+    pub ssid: Option<String>,
+    /// WLAN interfaces the round worked on
+    pub interfaces: usize,
+    /// Profiles a connect was attempted for, in order
+    pub attempts: Vec<String>,
+    /// Why the round failed
+    pub error: Option<String>,
+}
+
+impl RecoveryEvent {
+    /// Event for a finished round
+    pub fn from_round(
+        result: &anyhow::Result<ConnectedNetwork>,
+        report: &RecoveryReport,
+        duration: std::time::Duration,
+    ) -> Self {
+        let connected = result.as_ref().ok();
+        Self {
+            schema_version: SCHEMA_VERSION,
+            finished: state::unix_now(),
+            duration_ms: duration.as_millis() as u64,
+            restored: connected.is_some(),
+            profile: connected.map(|c| c.profile.clone()),
+            ssid: connected.map(|c| c.ssid.clone()).filter(|s| !s.is_empty()),
+            interfaces: report.interfaces,
+            attempts: report.attempts.iter().map(|a| a.profile.clone()).collect(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+        }
+    }
+}
+
+/// Attempt / success counters for one saved profile
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct ProfileStats {
+    pub attempts: u32,
+    pub successes: u32,
+    /// Local time of the last attempt that restored the network
+    pub last_success: Option<String>,
+}
+
+/// Statistics for all profiles the watchdog has tried: the `--stats-file` contents
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Stats {
+    /// `SCHEMA_VERSION` of the writer; 0 for files written before versioning
+    #[serde(default)]
+    pub schema_version: u32,
+    pub profiles: BTreeMap<String, ProfileStats>,
+    /// Profile that most recently restored the network (tried first in the next round)
+    #[serde(default)]
+    pub last_good: Option<String>,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            profiles: BTreeMap::new(),
+            last_good: None,
+        }
+    }
+}
+
+/// `--list-bss` output
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct BssReport {
+    pub schema_version: u32,
+    pub interfaces: Vec<InterfaceBssList>,
+}
+
+/// Every visible BSS of one interface, grouped by SSID
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct InterfaceBssList {
+    pub interface: String,
+    pub guid: String,
+    pub networks: Vec<NetworkBssList>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct NetworkBssList {
+    pub ssid: String,
+    pub bss: Vec<BssInfo>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct BssInfo {
+    pub bssid: String,
+    pub rssi: i32,
+    /// 0-100
+    pub link_quality: u32,
+    pub frequency_mhz: u32,
+    /// None for frequencies outside the 2.4/5/6 GHz bands
+    pub channel: Option<u32>,
+    /// PHY type such as `n`, `ac`, `ax`
+    pub phy: String,
+}
+
+/// `--print-schema`: JSON Schema of every shape, keyed by name
+pub fn print_schema() -> anyhow::Result<()> {
+    let schemas = serde_json::json!({
+        "schema_version": SCHEMA_VERSION,
+        "StatusSnapshot": schemars::schema_for!(StatusSnapshot),
+        "RecoveryEvent": schemars::schema_for!(RecoveryEvent),
+        "Stats": schemars::schema_for!(Stats),
+        "BssReport": schemars::schema_for!(BssReport),
+    });
+    println!("{}", serde_json::to_string_pretty(&schemas)?);
+    Ok(())
+}
@@ -0,0 +1,33 @@
+//! `--version`: crate version, build info and the running Windows build
+
+use windows::Wdk::System::SystemServices::RtlGetVersion;
+use windows::Win32::System::SystemInformation::OSVERSIONINFOW;
+
+/// Windows version as major.minor.build (RtlGetVersion is not subject to manifest shimming)
+fn windows_build() -> Option<String> {
+    let mut info = OSVERSIONINFOW {
+        dwOSVersionInfoSize: std::mem::size_of::<OSVERSIONINFOW>() as u32,
+        ..Default::default()
+    };
+    let status = unsafe { RtlGetVersion(&mut info) };
+    status.is_ok().then(|| {
+        format!(
+            "{}.{}.{}",
+            info.dwMajorVersion, info.dwMinorVersion, info.dwBuildNumber
+        )
+    })
+}
+
+/// Print version and build info, for pasting into issue reports
+pub fn print() {
+    println!(
+        "network-watchdog {} (commit {})",
+        env!("CARGO_PKG_VERSION"),
+        env!("WATCHDOG_GIT_COMMIT")
+    );
+    println!("windows crate {}", env!("WATCHDOG_WINDOWS_CRATE"));
+    println!(
+        "Windows {}",
+        windows_build().unwrap_or_else(|| "unknown".to_string())
+    );
+}
@@ -1,15 +1,38 @@
 //! WLAN client: enumerate interfaces, saved profiles, connect
 
 use crate::adapter;
-use std::collections::HashSet;
+use crate::eap::{self, EapCredentials};
+use crate::ipconfig::{self, IpConfig};
+use crate::route;
+use crate::types::{BssInfo, BssReport, InterfaceBssList, NetworkBssList, SCHEMA_VERSION};
+use std::collections::{HashMap, HashSet};
 use std::ptr::NonNull;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
 use windows::core::PCWSTR;
-use windows::Win32::Foundation::HANDLE;
+use windows::Win32::Foundation::{
+    ERROR_BUSY, ERROR_DEVICE_NOT_CONNECTED, ERROR_DEVICE_REMOVED, ERROR_NOT_FOUND, HANDLE,
+};
+use windows::Win32::NetworkManagement::Ndis::{NDIS_OBJECT_HEADER, NDIS_OBJECT_TYPE_DEFAULT};
 use windows::Win32::NetworkManagement::WiFi::{
-    dot11_BSS_type_any, wlan_connection_mode_profile, wlan_interface_state_connected,
-    wlan_intf_opcode_interface_state, WlanCloseHandle, WlanConnect, WlanEnumInterfaces,
-    WlanFreeMemory, WlanGetAvailableNetworkList, WlanGetProfileList, WlanOpenHandle,
-    WlanQueryInterface, WlanScan, WLAN_CONNECTION_PARAMETERS, WLAN_INTERFACE_STATE,
+    dot11_BSS_type_any, dot11_BSS_type_infrastructure, wlan_connection_mode_auto,
+    wlan_connection_mode_profile, wlan_connection_mode_temporary_profile,
+    wlan_interface_state_ad_hoc_network_formed, wlan_interface_state_associating,
+    wlan_interface_state_authenticating, wlan_interface_state_connected,
+    wlan_interface_state_disconnected, wlan_interface_state_disconnecting,
+    wlan_interface_state_discovering, wlan_interface_state_not_ready,
+    wlan_intf_opcode_autoconf_enabled, wlan_intf_opcode_background_scan_enabled,
+    wlan_intf_opcode_current_connection, wlan_intf_opcode_interface_state, WlanCloseHandle,
+    WlanConnect, WlanDisconnect, WlanEnumInterfaces, WlanFreeMemory, WlanGetAvailableNetworkList,
+    WlanGetNetworkBssList, WlanGetProfile, WlanGetProfileList, WlanOpenHandle, WlanQueryInterface,
+    WlanScan, WlanSetInterface, WlanSetProfileEapXmlUserData, DOT11_BSSID_LIST,
+    DOT11_BSSID_LIST_REVISION_1, DOT11_SSID, WLAN_CONNECTION_ADHOC_JOIN_ONLY,
+    WLAN_CONNECTION_ATTRIBUTES, WLAN_CONNECTION_EAPOL_PASSTHROUGH, WLAN_CONNECTION_HIDDEN_NETWORK,
+    WLAN_CONNECTION_IGNORE_PRIVACY_BIT, WLAN_CONNECTION_PARAMETERS, WLAN_INTERFACE_STATE,
+    WLAN_PROFILE_GET_PLAINTEXT_KEY, WLAN_SET_EAPHOST_FLAGS,
 };
 
 /// WLAN client handle wrapper
@@ -33,25 +56,176 @@ impl WlanClient {
         }
     }
 
-    /// Connect to the given profile on the given interface
+    /// Connect to the given profile on the given interface (`flags`: WLAN_CONNECTION_*, usually 0)
     pub fn connect_profile(
         &self,
         iface: &windows::core::GUID,
         profile: &str,
+        flags: u32,
+    ) -> anyhow::Result<()> {
+        self.connect(iface, profile, None, &[], flags)
+    }
+
+    /// Connect to the given profile, restricted to the given BSSIDs (access points)
+    pub fn connect_profile_to_bssids(
+        &self,
+        iface: &windows::core::GUID,
+        profile: &str,
+        bssids: &[[u8; 6]],
+        flags: u32,
+    ) -> anyhow::Result<()> {
+        self.connect(iface, profile, None, bssids, flags)
+    }
+
+    /// Connect using `profile_xml` as a session-only profile: the saved profile list and
+    /// its auto-connect order are left untouched
+    pub fn connect_temporary(
+        &self,
+        iface: &windows::core::GUID,
+        profile: &str,
+        profile_xml: &str,
+        bssids: &[[u8; 6]],
+        flags: u32,
+    ) -> anyhow::Result<()> {
+        self.connect(iface, profile, Some(profile_xml), bssids, flags)
+    }
+
+    /// Ask AutoConfig to connect on its own (wlan_connection_mode_auto): Windows picks the
+    /// profile by its auto-connect priority
+    pub fn connect_auto(&self, iface: &windows::core::GUID) -> anyhow::Result<()> {
+        let params = WLAN_CONNECTION_PARAMETERS {
+            wlanConnectionMode: wlan_connection_mode_auto,
+            strProfile: PCWSTR::null(),
+            pDot11Ssid: std::ptr::null_mut(),
+            pDesiredBssidList: std::ptr::null_mut(),
+            dot11BssType: dot11_BSS_type_any,
+            dwFlags: 0,
+        };
+        let status = unsafe { WlanConnect(self.handle, iface, &params, None) };
+        if status != 0 {
+            anyhow::bail!("WlanConnect(auto) failed: {}", status);
+        }
+        Ok(())
+    }
+
+    /// Disconnect the interface from its current network
+    pub fn disconnect(&self, iface: &windows::core::GUID) -> anyhow::Result<()> {
+        let status = unsafe { WlanDisconnect(self.handle, iface, None) };
+        if status != 0 {
+            anyhow::bail!("WlanDisconnect failed: {}", status);
+        }
+        Ok(())
+    }
+
+    /// Store EAP user credentials (EapHostUserCredentials XML) for an 802.1X profile
+    pub fn set_eap_user_data(
+        &self,
+        iface: &windows::core::GUID,
+        profile: &str,
+        user_data_xml: &str,
+    ) -> anyhow::Result<()> {
+        let name: Vec<u16> = profile.encode_utf16().chain(std::iter::once(0)).collect();
+        let data: Vec<u16> = user_data_xml
+            .encode_utf16()
+            .chain(std::iter::once(0))
+            .collect();
+        let status = unsafe {
+            WlanSetProfileEapXmlUserData(
+                self.handle,
+                iface,
+                PCWSTR::from_raw(name.as_ptr()),
+                WLAN_SET_EAPHOST_FLAGS(0),
+                PCWSTR::from_raw(data.as_ptr()),
+                None,
+            )
+        };
+        if status != 0 {
+            anyhow::bail!(
+                "WlanSetProfileEapXmlUserData({}) failed: {}",
+                profile,
+                status
+            );
+        }
+        Ok(())
+    }
+
+    /// WlanConnect in profile mode, or temporary-profile mode when `profile_xml` is given;
+    /// a non-empty `bssids` fills pDesiredBssidList, `flags` goes to dwFlags.
+    /// The SSID from the profile XML goes to pDot11Ssid so a profile whose name collides with
+    /// another network's cannot associate with the wrong SSID.
+    fn connect(
+        &self,
+        iface: &windows::core::GUID,
+        profile: &str,
+        profile_xml: Option<&str>,
+        bssids: &[[u8; 6]],
+        flags: u32,
     ) -> anyhow::Result<()> {
         unsafe {
-            let wide: Vec<u16> = profile.encode_utf16().chain(std::iter::once(0)).collect();
+            let wide: Vec<u16> = profile_xml
+                .unwrap_or(profile)
+                .encode_utf16()
+                .chain(std::iter::once(0))
+                .collect();
+            // DOT11_BSSID_LIST ends in a variable-length BSSID array; back it with a u32 buffer
+            // so the header stays aligned
+            let list_size =
+                std::mem::size_of::<DOT11_BSSID_LIST>() + bssids.len().saturating_sub(1) * 6;
+            let mut list_buf = vec![0u32; list_size.div_ceil(4)];
+            let bssid_list = if bssids.is_empty() {
+                std::ptr::null_mut()
+            } else {
+                let list = list_buf.as_mut_ptr().cast::<DOT11_BSSID_LIST>();
+                (*list).Header = NDIS_OBJECT_HEADER {
+                    Type: NDIS_OBJECT_TYPE_DEFAULT as u8,
+                    Revision: DOT11_BSSID_LIST_REVISION_1 as u8,
+                    Size: std::mem::size_of::<DOT11_BSSID_LIST>() as u16,
+                };
+                (*list).uNumOfEntries = bssids.len() as u32;
+                (*list).uTotalNumOfEntries = bssids.len() as u32;
+                let dst = std::ptr::addr_of_mut!((*list).BSSIDs).cast::<[u8; 6]>();
+                for (i, bssid) in bssids.iter().enumerate() {
+                    *dst.add(i) = *bssid;
+                }
+                list
+            };
+            let xml = match profile_xml {
+                Some(xml) => Some(xml.to_string()),
+                None => get_profile_xml(self.handle, iface, profile, false).ok(),
+            };
+            let mut ssid = xml.as_deref().and_then(profile_ssid).map(|bytes| {
+                let mut ssid = DOT11_SSID {
+                    uSSIDLength: bytes.len() as u32,
+                    ..Default::default()
+                };
+                ssid.ucSSID[..bytes.len()].copy_from_slice(&bytes);
+                ssid
+            });
             let params = WLAN_CONNECTION_PARAMETERS {
-                wlanConnectionMode: wlan_connection_mode_profile,
+                wlanConnectionMode: if profile_xml.is_some() {
+                    wlan_connection_mode_temporary_profile
+                } else {
+                    wlan_connection_mode_profile
+                },
                 strProfile: PCWSTR::from_raw(wide.as_ptr()),
-                pDot11Ssid: std::ptr::null_mut(),
-                pDesiredBssidList: std::ptr::null_mut(),
-                dot11BssType: dot11_BSS_type_any,
-                dwFlags: 0,
+                pDot11Ssid: ssid
+                    .as_mut()
+                    .map_or(std::ptr::null_mut(), |s| s as *mut DOT11_SSID),
+                pDesiredBssidList: bssid_list,
+                // A desired BSSID list is only valid for infrastructure networks
+                dot11BssType: if bssids.is_empty() {
+                    dot11_BSS_type_any
+                } else {
+                    dot11_BSS_type_infrastructure
+                },
+                dwFlags: flags,
             };
 
             let status = WlanConnect(self.handle, iface, &params, None);
 
+            if is_removal_status(status) {
+                return Err(InterfaceRemoved { status }.into());
+            }
             if status != 0 {
                 anyhow::bail!("WlanConnect({}) failed: {}", profile, status);
             }
@@ -69,8 +243,10 @@ impl Drop for WlanClient {
     }
 }
 
-/// Get all WLAN interface GUIDs
-unsafe fn get_wlan_interfaces(handle: HANDLE) -> anyhow::Result<Vec<windows::core::GUID>> {
+/// Get all WLAN interfaces as (GUID, description) pairs
+unsafe fn get_wlan_interfaces(
+    handle: HANDLE,
+) -> anyhow::Result<Vec<(windows::core::GUID, String)>> {
     let mut list = std::ptr::null_mut();
     let status = WlanEnumInterfaces(handle, None, &mut list);
 
@@ -84,8 +260,11 @@ unsafe fn get_wlan_interfaces(handle: HANDLE) -> anyhow::Result<Vec<windows::cor
 
     let interfaces: Vec<_> = (0..count)
         .map(|i| {
-            let base = list.as_ref().InterfaceInfo.as_ptr();
-            (*base.add(i)).InterfaceGuid
+            let info = &*list.as_ref().InterfaceInfo.as_ptr().add(i);
+            (
+                info.InterfaceGuid,
+                wide_to_string(&info.strInterfaceDescription),
+            )
         })
         .collect();
 
@@ -105,17 +284,122 @@ fn dot11_ssid_to_string(ssid: &windows::Win32::NetworkManagement::WiFi::DOT11_SS
     String::from_utf8_lossy(&ssid.ucSSID[..len]).into_owned()
 }
 
+/// BSSID as colon-separated hex
+fn format_bssid(bssid: &[u8; 6]) -> String {
+    bssid
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Time the driver gets to complete a scan before its results are read
+const SCAN_SETTLE: tokio::time::Duration = tokio::time::Duration::from_secs(2);
+
+/// When each interface was last scanned (for --scan-min-interval)
+static LAST_SCAN: Mutex<Vec<(windows::core::GUID, Instant)>> = Mutex::new(Vec::new());
+
+/// WlanScan the interface `passes` times, waiting for results after each, unless it was
+/// scanned within `min_interval` or the driver refuses (ERROR_BUSY when throttled): then the
+/// available-network list still holds the previous scan's results and is used as is, without
+/// waiting. The driver merges what each pass saw, so the list is read once afterwards.
+async fn scan_and_wait(
+    handle: HANDLE,
+    iface: &windows::core::GUID,
+    min_interval: Duration,
+    passes: u32,
+) {
+    {
+        let last = LAST_SCAN.lock().unwrap();
+        if let Some((_, at)) = last.iter().find(|(g, _)| g == iface) {
+            if at.elapsed() < min_interval {
+                tracing::debug!(
+                    "Scanned {:.1}s ago, reusing the available-network list",
+                    at.elapsed().as_secs_f64()
+                );
+                return;
+            }
+        }
+    }
+    for pass in 1..=passes.max(1) {
+        let status = unsafe { WlanScan(handle, iface, None, None, None) };
+        if status != 0 {
+            if status == ERROR_BUSY.0 {
+                tracing::info!("WlanScan busy (throttled), reusing the last scan results");
+            } else {
+                tracing::debug!("WlanScan failed: {}, reusing the last scan results", status);
+            }
+            return;
+        }
+        {
+            let mut last = LAST_SCAN.lock().unwrap();
+            last.retain(|(g, _)| g != iface);
+            last.push((*iface, Instant::now()));
+        }
+        if passes > 1 {
+            tracing::debug!("Scan pass {}/{}", pass, passes);
+        }
+        tokio::time::sleep(SCAN_SETTLE).await;
+    }
+}
+
+/// A network name seen by a recent scan (for --scan-memory)
+struct SeenNetwork {
+    iface: windows::core::GUID,
+    name: String,
+    seen: Instant,
+    /// Signal quality, when the name is a saved profile
+    signal: Option<u32>,
+}
+
+/// Network names seen in recent scans, all interfaces
+static SCAN_MEMORY: Mutex<Vec<SeenNetwork>> = Mutex::new(Vec::new());
+
+/// `--scan-memory`: remember what this scan saw, and add names seen on the interface within
+/// `ttl` that the current scan missed (intermittently visible APs), with their last signal
+fn merge_scan_memory(
+    iface: &windows::core::GUID,
+    names: &mut HashSet<String>,
+    signals: &mut HashMap<String, u32>,
+    ttl: Duration,
+) {
+    let now = Instant::now();
+    let mut memory = SCAN_MEMORY.lock().unwrap();
+    memory.retain(|m| {
+        now.duration_since(m.seen) < ttl && !(m.iface == *iface && names.contains(&m.name))
+    });
+    let mut remembered = Vec::new();
+    for m in memory.iter().filter(|m| m.iface == *iface) {
+        names.insert(m.name.clone());
+        if let Some(signal) = m.signal {
+            signals.entry(m.name.clone()).or_insert(signal);
+        }
+        remembered.push(m.name.clone());
+    }
+    for name in names.iter().filter(|n| !remembered.contains(n)) {
+        memory.push(SeenNetwork {
+            iface: *iface,
+            name: name.clone(),
+            seen: now,
+            signal: signals.get(name).copied(),
+        });
+    }
+    if !remembered.is_empty() {
+        tracing::info!(
+            "Scan memory: {} network(s) missing from this scan but seen in the last {}s: {:?}",
+            remembered.len(),
+            ttl.as_secs(),
+            remembered
+        );
+    }
+}
+
 /// Get set of currently visible (in-range) network names: SSID strings + existing profile names.
-/// Optionally trigger a scan first to refresh the list.
+/// Reads the driver's current list; call `scan_and_wait` first to refresh it.
 unsafe fn get_available_network_names(
     handle: HANDLE,
     iface: &windows::core::GUID,
-    trigger_scan: bool,
 ) -> anyhow::Result<HashSet<String>> {
-    if trigger_scan {
-        let _ = WlanScan(handle, iface, None, None, None);
-        // Caller decides whether to sleep
-    }
     let mut list = std::ptr::null_mut();
     // dwflags 0 = default
     let status = WlanGetAvailableNetworkList(handle, iface, 0, None, &mut list);
@@ -142,6 +426,135 @@ unsafe fn get_available_network_names(
     Ok(names)
 }
 
+/// Saved profiles visible on the interface with their best signal quality (0-100),
+/// strongest first. Uses the last scan results.
+unsafe fn get_visible_saved_networks(
+    handle: HANDLE,
+    iface: &windows::core::GUID,
+) -> anyhow::Result<Vec<(String, u32)>> {
+    let mut list = std::ptr::null_mut();
+    let status = WlanGetAvailableNetworkList(handle, iface, 0, None, &mut list);
+    if status != 0 {
+        anyhow::bail!("WlanGetAvailableNetworkList failed: {}", status);
+    }
+    let list = NonNull::new(list)
+        .ok_or_else(|| anyhow::anyhow!("WlanGetAvailableNetworkList returned null"))?;
+    let count = list.as_ref().dwNumberOfItems as usize;
+    let mut networks: Vec<(String, u32)> = Vec::new();
+    for i in 0..count {
+        let net = &*list.as_ref().Network.as_ptr().add(i);
+        let profile = wide_to_string(&net.strProfileName);
+        if profile.is_empty() {
+            continue;
+        }
+        match networks.iter_mut().find(|(p, _)| *p == profile) {
+            Some(entry) => entry.1 = entry.1.max(net.wlanSignalQuality),
+            None => networks.push((profile, net.wlanSignalQuality)),
+        }
+    }
+    WlanFreeMemory(list.as_ptr().cast());
+    networks.sort_by_key(|(_, signal)| std::cmp::Reverse(*signal));
+    Ok(networks)
+}
+
+/// One visible BSS (access point)
+struct BssEntry {
+    ssid: String,
+    bssid: [u8; 6],
+    rssi: i32,
+    link_quality: u32,
+    frequency_khz: u32,
+    phy_type: i32,
+}
+
+/// Get all BSS entries currently visible on the interface (from the driver's scan cache)
+unsafe fn get_bss_list(
+    handle: HANDLE,
+    iface: &windows::core::GUID,
+) -> anyhow::Result<Vec<BssEntry>> {
+    let mut list = std::ptr::null_mut();
+    let status = WlanGetNetworkBssList(
+        handle,
+        iface,
+        None,
+        dot11_BSS_type_any,
+        false,
+        None,
+        &mut list,
+    );
+    if status != 0 {
+        anyhow::bail!("WlanGetNetworkBssList failed: {}", status);
+    }
+    let list =
+        NonNull::new(list).ok_or_else(|| anyhow::anyhow!("WlanGetNetworkBssList returned null"))?;
+    let count = list.as_ref().dwNumberOfItems as usize;
+    let entries = (0..count)
+        .map(|i| {
+            let base = list.as_ref().wlanBssEntries.as_ptr();
+            let e = &*base.add(i);
+            BssEntry {
+                ssid: dot11_ssid_to_string(&e.dot11Ssid),
+                bssid: e.dot11Bssid,
+                rssi: e.lRssi,
+                link_quality: e.uLinkQuality,
+                frequency_khz: e.ulChCenterFrequency,
+                phy_type: e.dot11BssPhyType.0,
+            }
+        })
+        .collect();
+    WlanFreeMemory(list.as_ptr().cast());
+    Ok(entries)
+}
+
+/// Error: no Wi-Fi hardware at all (not just a disabled adapter); recovery cannot work
+#[derive(Debug)]
+pub struct NoWifiHardware;
+
+impl std::fmt::Display for NoWifiHardware {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "No Wi-Fi hardware detected")
+    }
+}
+
+impl std::error::Error for NoWifiHardware {}
+
+/// Error: a WLAN call failed because the interface is gone (e.g. a USB adapter was unplugged)
+#[derive(Debug)]
+pub struct InterfaceRemoved {
+    pub status: u32,
+}
+
+impl std::fmt::Display for InterfaceRemoved {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "WLAN interface no longer present (status {})",
+            self.status
+        )
+    }
+}
+
+impl std::error::Error for InterfaceRemoved {}
+
+/// WLAN status codes meaning the interface GUID no longer refers to a device
+fn is_removal_status(status: u32) -> bool {
+    [
+        ERROR_NOT_FOUND,
+        ERROR_DEVICE_NOT_CONNECTED,
+        ERROR_DEVICE_REMOVED,
+    ]
+    .iter()
+    .any(|e| e.0 == status)
+}
+
+/// Whether `iface` is still among the enumerated WLAN interfaces
+fn interface_present(client: &WlanClient, iface: &windows::core::GUID) -> bool {
+    unsafe { get_wlan_interfaces(client.handle) }
+        .map(|ifaces| ifaces.iter().any(|(g, _)| g == iface))
+        // Enumeration failing says nothing about this interface; keep going
+        .unwrap_or(true)
+}
+
 /// Connect strategy: visible only / all saved / explicit list
 #[derive(Clone, Debug)]
 pub enum ConnectStrategy {
@@ -153,6 +566,72 @@ pub enum ConnectStrategy {
     Explicit(Vec<String>),
 }
 
+/// Flag names accepted by `--connect-flags` and their WLAN_CONNECTION_* values
+const CONNECT_FLAG_NAMES: &[(&str, u32)] = &[
+    ("hidden", WLAN_CONNECTION_HIDDEN_NETWORK),
+    ("ignore-privacy", WLAN_CONNECTION_IGNORE_PRIVACY_BIT),
+    ("adhoc-join-only", WLAN_CONNECTION_ADHOC_JOIN_ONLY),
+    ("eapol-passthrough", WLAN_CONNECTION_EAPOL_PASSTHROUGH),
+];
+
+/// `--connect-flags "<profile>=hidden,ignore-privacy"`: WlanConnect dwFlags for one profile
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProfileConnectFlags {
+    pub profile: String,
+    pub flags: u32,
+}
+
+impl FromStr for ProfileConnectFlags {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Split at the last '=' so profile names may contain one
+        let (profile, names) = s
+            .rsplit_once('=')
+            .ok_or_else(|| format!("expected <profile>=<flag>[,<flag>...], got \"{}\"", s))?;
+        if profile.is_empty() {
+            return Err(format!("missing profile name in \"{}\"", s));
+        }
+        let mut flags = 0;
+        for name in names.split(',').map(str::trim) {
+            let Some(&(_, value)) = CONNECT_FLAG_NAMES.iter().find(|(n, _)| *n == name) else {
+                let supported: Vec<&str> = CONNECT_FLAG_NAMES.iter().map(|(n, _)| *n).collect();
+                return Err(format!(
+                    "unknown connect flag \"{}\" (supported: {})",
+                    name,
+                    supported.join(", ")
+                ));
+            };
+            flags |= value;
+        }
+        Ok(Self {
+            profile: profile.to_string(),
+            flags,
+        })
+    }
+}
+
+/// Order in which candidate profiles are tried (`--profile-order`)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ProfileOrder {
+    /// Strongest signal first, ties by saved-profile priority
+    #[default]
+    Signal,
+    /// The exact opposite: profiles not in range, then weakest signal first
+    Reverse,
+}
+
+/// How recovery asks WlanConnect for a connection (`--connect-mode`)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ConnectMode {
+    /// Connect each candidate saved profile by name
+    #[default]
+    Profile,
+    /// Only kick AutoConfig (wlan_connection_mode_auto) and let Windows pick among its
+    /// preferred networks
+    Auto,
+}
+
 /// Get all saved profile names for the given interface
 unsafe fn get_saved_profiles(
     handle: HANDLE,
@@ -180,6 +659,241 @@ unsafe fn get_saved_profiles(
     Ok(profiles)
 }
 
+/// Get a saved profile's XML; `plaintext_key` returns key material unencrypted (requires admin)
+unsafe fn get_profile_xml(
+    handle: HANDLE,
+    iface: &windows::core::GUID,
+    profile: &str,
+    plaintext_key: bool,
+) -> anyhow::Result<String> {
+    let wide: Vec<u16> = profile.encode_utf16().chain(std::iter::once(0)).collect();
+    let mut xml = windows::core::PWSTR::null();
+    let mut flags = if plaintext_key {
+        WLAN_PROFILE_GET_PLAINTEXT_KEY
+    } else {
+        0
+    };
+    let status = WlanGetProfile(
+        handle,
+        iface,
+        PCWSTR::from_raw(wide.as_ptr()),
+        None,
+        &mut xml,
+        Some(&mut flags),
+        None,
+    );
+    if status != 0 {
+        anyhow::bail!("WlanGetProfile({}) failed: {}", profile, status);
+    }
+    if xml.is_null() {
+        anyhow::bail!("WlanGetProfile({}) returned null", profile);
+    }
+    let text = xml.to_string();
+    WlanFreeMemory(xml.as_ptr().cast());
+    Ok(text?)
+}
+
+/// SSID bytes of the first `<SSID>` element of a profile XML: its `<hex>` form when present
+/// (exact bytes), else `<name>`. None if missing, empty or longer than 32 bytes.
+fn profile_ssid(xml: &str) -> Option<Vec<u8>> {
+    let start = xml.find("<SSID>")?;
+    let end = start + xml[start..].find("</SSID>")?;
+    let element = &xml[start..end];
+    let inner = |tag: &str| {
+        let open = format!("<{}>", tag);
+        let body = element.find(&open)? + open.len();
+        let len = element[body..].find(&format!("</{}>", tag))?;
+        Some(element[body..body + len].trim())
+    };
+    let bytes = match inner("hex") {
+        Some(hex) if hex.len() % 2 == 0 => (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<u8>>>()?,
+        _ => inner("name")?
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&")
+            .into_bytes(),
+    };
+    (!bytes.is_empty() && bytes.len() <= 32).then_some(bytes)
+}
+
+/// Replace the contents of every `<keyMaterial>` element with asterisks
+fn mask_key_material(xml: &str) -> String {
+    const OPEN: &str = "<keyMaterial>";
+    const CLOSE: &str = "</keyMaterial>";
+    let mut out = String::with_capacity(xml.len());
+    let mut rest = xml;
+    while let Some(start) = rest.find(OPEN) {
+        let body = start + OPEN.len();
+        let Some(len) = rest[body..].find(CLOSE) else {
+            break;
+        };
+        out.push_str(&rest[..body]);
+        out.push_str("********");
+        rest = &rest[body + len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// `--print-profile-xml`: XML of the named saved profile from the first interface that has it.
+/// Key material is masked unless `show_key` (then fetched in plain text; requires admin).
+pub fn saved_profile_xml(profile: &str, show_key: bool) -> anyhow::Result<String> {
+    let client = WlanClient::new()?;
+    let ifaces = unsafe { get_wlan_interfaces(client.handle)? };
+    for (iface, _) in &ifaces {
+        let saved = unsafe { get_saved_profiles(client.handle, iface) }.unwrap_or_default();
+        if saved.iter().any(|p| p == profile) {
+            let xml = unsafe { get_profile_xml(client.handle, iface, profile, show_key)? };
+            return Ok(if show_key {
+                xml
+            } else {
+                mask_key_material(&xml)
+            });
+        }
+    }
+    anyhow::bail!(
+        "Profile \"{}\" not found on {} WLAN interface(s)",
+        profile,
+        ifaces.len()
+    )
+}
+
+/// 802.11 channel number for a center frequency in MHz
+fn channel_for_frequency(mhz: u32) -> Option<u32> {
+    match mhz {
+        2484 => Some(14),
+        2412..=2472 => Some((mhz - 2407) / 5),
+        5955..=7115 => Some((mhz - 5950) / 5),
+        5160..=5885 => Some((mhz - 5000) / 5),
+        _ => None,
+    }
+}
+
+/// Wi-Fi frequency band (`--band-only`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Band {
+    #[value(name = "2.4")]
+    Ghz2_4,
+    #[value(name = "5")]
+    Ghz5,
+    #[value(name = "6")]
+    Ghz6,
+}
+
+impl Band {
+    /// Whether a BSS centre frequency (kHz) lies in this band
+    fn contains(self, frequency_khz: u32) -> bool {
+        let mhz = frequency_khz / 1000;
+        match self {
+            Band::Ghz2_4 => (2400..2500).contains(&mhz),
+            Band::Ghz5 => (4900..5925).contains(&mhz),
+            Band::Ghz6 => (5925..7125).contains(&mhz),
+        }
+    }
+}
+
+impl std::fmt::Display for Band {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Band::Ghz2_4 => "2.4 GHz",
+            Band::Ghz5 => "5 GHz",
+            Band::Ghz6 => "6 GHz",
+        })
+    }
+}
+
+/// `--band-only`: BSSIDs of the profile's SSID visible in `band`, for pDesiredBssidList
+/// (WlanConnect has no band filter). Err when the SSID cannot be determined or no such BSS is
+/// visible, with the reason to log.
+fn band_bssids(
+    client: &WlanClient,
+    iface: &windows::core::GUID,
+    profile: &str,
+    band: Band,
+) -> Result<Vec<[u8; 6]>, String> {
+    let ssid = unsafe { get_profile_xml(client.handle, iface, profile, false) }
+        .ok()
+        .as_deref()
+        .and_then(profile_ssid)
+        .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+        .ok_or_else(|| format!("SSID of \"{}\" unknown", profile))?;
+    let bss_list = unsafe { get_bss_list(client.handle, iface) }.map_err(|e| format!("{}", e))?;
+    let bssids: Vec<[u8; 6]> = bss_list
+        .iter()
+        .filter(|b| b.ssid == ssid && band.contains(b.frequency_khz))
+        .map(|b| b.bssid)
+        .collect();
+    if bssids.is_empty() {
+        return Err(format!("no {} BSS visible for \"{}\"", band, ssid));
+    }
+    Ok(bssids)
+}
+
+/// Short name of a DOT11_PHY_TYPE value (the 802.11 amendment)
+fn phy_name(phy_type: i32) -> &'static str {
+    match phy_type {
+        1 => "fhss",
+        2 => "dsss",
+        3 => "irbaseband",
+        4 => "a",
+        5 => "b",
+        6 => "g",
+        7 => "n",
+        8 => "ac",
+        9 => "ad",
+        10 => "ax",
+        11 => "be",
+        _ => "unknown",
+    }
+}
+
+/// `--list-bss`: full BSS list of every WLAN interface from the driver's scan cache (no scan
+/// is triggered), strongest BSS first within each SSID
+pub fn bss_report() -> anyhow::Result<BssReport> {
+    let client = WlanClient::new()?;
+    let ifaces = unsafe { get_wlan_interfaces(client.handle)? };
+    let mut report = Vec::new();
+    for (iface, description) in ifaces {
+        let mut networks: Vec<NetworkBssList> = Vec::new();
+        for entry in unsafe { get_bss_list(client.handle, &iface)? } {
+            let frequency_mhz = entry.frequency_khz / 1000;
+            let info = BssInfo {
+                bssid: format_bssid(&entry.bssid),
+                rssi: entry.rssi,
+                link_quality: entry.link_quality,
+                frequency_mhz,
+                channel: channel_for_frequency(frequency_mhz),
+                phy: phy_name(entry.phy_type).to_string(),
+            };
+            match networks.iter_mut().find(|n| n.ssid == entry.ssid) {
+                Some(network) => network.bss.push(info),
+                None => networks.push(NetworkBssList {
+                    ssid: entry.ssid,
+                    bss: vec![info],
+                }),
+            }
+        }
+        for network in &mut networks {
+            network.bss.sort_by_key(|b| std::cmp::Reverse(b.rssi));
+        }
+        networks.sort_by_key(|n| std::cmp::Reverse(n.bss[0].rssi));
+        report.push(InterfaceBssList {
+            interface: description,
+            guid: format!("{:?}", iface),
+            networks,
+        });
+    }
+    Ok(BssReport {
+        schema_version: SCHEMA_VERSION,
+        interfaces: report,
+    })
+}
+
 /// Query current WLAN interface state (connected / associating / disconnected etc.)
 unsafe fn get_wlan_interface_state(
     handle: HANDLE,
@@ -204,67 +918,1269 @@ unsafe fn get_wlan_interface_state(
     Some(state)
 }
 
-/// Poll WLAN interface connection state until \"connected\" or timeout. Uses connection state, not NCSI.
-async fn poll_wlan_connection_state(
+/// Count interfaces whose WLAN AutoConfig is disabled (WlanConnect cannot work on them)
+pub fn autoconf_disabled_interfaces() -> anyhow::Result<usize> {
+    let client = WlanClient::new()?;
+    let ifaces = unsafe { get_wlan_interfaces(client.handle)? };
+    let disabled = ifaces
+        .iter()
+        .filter(|(iface, _)| unsafe {
+            let mut size = 0u32;
+            let mut pdata = std::ptr::null_mut();
+            let status = WlanQueryInterface(
+                client.handle,
+                iface,
+                wlan_intf_opcode_autoconf_enabled,
+                None,
+                &mut size,
+                &mut pdata,
+                None,
+            );
+            if status != 0 || pdata.is_null() {
+                return false;
+            }
+            let enabled = *pdata.cast::<i32>() != 0;
+            WlanFreeMemory(pdata);
+            !enabled
+        })
+        .count();
+    Ok(disabled)
+}
+
+/// Profile and SSID of the current connection on an interface
+#[derive(Clone, Debug)]
+pub struct ConnectedNetwork {
+    pub profile: String,
+    pub ssid: String,
+    pub bssid: [u8; 6],
+    /// Signal quality, 0-100
+    pub signal_quality: u32,
+    /// Addresses, gateways and DNS servers (filled for reported connections only)
+    pub ip: Option<IpConfig>,
+}
+
+/// Query the interface's current connection attributes and map them with `f`
+/// (None if not connected or query failed)
+unsafe fn with_connection_attributes<T>(
     handle: HANDLE,
     iface: &windows::core::GUID,
-    max_wait_secs: u64,
-    interval_secs: u64,
-) -> bool {
-    let rounds = (max_wait_secs / interval_secs).max(1);
-    for round in 1..=rounds {
-        tokio::time::sleep(tokio::time::Duration::from_secs(interval_secs)).await;
-        let state = unsafe { get_wlan_interface_state(handle, iface) };
-        tracing::info!(
-            "WLAN state poll #{}/{} ({}s/{}s): {:?}",
-            round,
-            rounds,
-            round * interval_secs,
-            max_wait_secs,
-            state
-        );
-        if state == Some(wlan_interface_state_connected) {
-            return true;
-        }
+    f: impl FnOnce(&WLAN_CONNECTION_ATTRIBUTES) -> T,
+) -> Option<T> {
+    let mut size = 0u32;
+    let mut pdata = std::ptr::null_mut();
+    let status = WlanQueryInterface(
+        handle,
+        iface,
+        wlan_intf_opcode_current_connection,
+        None,
+        &mut size,
+        &mut pdata,
+        None,
+    );
+    if status != 0 || pdata.is_null() {
+        return None;
     }
-    false
+    let value = f(&*pdata.cast::<WLAN_CONNECTION_ATTRIBUTES>());
+    WlanFreeMemory(pdata);
+    Some(value)
 }
 
-/// Filter profiles by strategy: only those we should try
-fn filter_profiles_by_strategy(
-    saved: &[String],
-    strategy: &ConnectStrategy,
-    available_names: Option<&HashSet<String>>,
-) -> Vec<String> {
-    match strategy {
-        ConnectStrategy::ScanOnly => {
-            let avail = match available_names {
-                Some(s) => s,
-                None => return Vec::new(),
+/// Query the interface's current connection (None if not connected or query failed)
+unsafe fn get_current_connection(
+    handle: HANDLE,
+    iface: &windows::core::GUID,
+) -> Option<ConnectedNetwork> {
+    with_connection_attributes(handle, iface, |attrs| ConnectedNetwork {
+        profile: wide_to_string(&attrs.strProfileName),
+        ssid: dot11_ssid_to_string(&attrs.wlanAssociationAttributes.dot11Ssid),
+        bssid: attrs.wlanAssociationAttributes.dot11Bssid,
+        signal_quality: attrs.wlanAssociationAttributes.wlanSignalQuality,
+        ip: None,
+    })
+}
+
+/// `--suspend-autoconnect` guard: background scanning, which drives Windows auto-connect, is
+/// off on the interfaces until the guard is dropped (also when the round errors or is aborted).
+/// AutoConfig itself stays enabled since WlanConnect needs it.
+struct AutoconnectPause<'a> {
+    client: &'a WlanClient,
+    paused: Vec<windows::core::GUID>,
+}
+
+impl<'a> AutoconnectPause<'a> {
+    fn new(client: &'a WlanClient, ifaces: &[(windows::core::GUID, String)]) -> Self {
+        let mut paused = Vec::new();
+        for (iface, desc) in ifaces {
+            let enabled = unsafe {
+                let mut size = 0u32;
+                let mut pdata = std::ptr::null_mut();
+                let status = WlanQueryInterface(
+                    client.handle,
+                    iface,
+                    wlan_intf_opcode_background_scan_enabled,
+                    None,
+                    &mut size,
+                    &mut pdata,
+                    None,
+                );
+                if status != 0 || pdata.is_null() {
+                    false
+                } else {
+                    let enabled = *pdata.cast::<i32>() != 0;
+                    WlanFreeMemory(pdata);
+                    enabled
+                }
             };
-            saved
-                .iter()
-                .filter(|p| avail.contains(*p))
-                .cloned()
-                .collect()
-        }
-        ConnectStrategy::All => saved.to_vec(),
-        ConnectStrategy::Explicit(names) => {
-            let set: HashSet<_> = names.iter().map(String::as_str).collect();
-            saved
-                .iter()
-                .filter(|p| set.contains(p.as_str()))
-                .cloned()
-                .collect()
+            if !enabled {
+                continue;
+            }
+            match set_background_scan(client, iface, false) {
+                Ok(()) => {
+                    tracing::info!("Auto-connect suspended on {} during recovery", desc);
+                    paused.push(*iface);
+                }
+                Err(e) => tracing::warn!("Could not suspend auto-connect on {}: {}", desc, e),
+            }
         }
+        Self { client, paused }
     }
 }
 
-/// Enumerate saved profiles, filter by strategy, try connecting until NCSI passes
-pub async fn connect_any_saved_wifi(
-    test_network: impl Fn() -> std::pin::Pin<Box<dyn std::future::Future<Output = bool> + Send>>,
-    strategy: ConnectStrategy,
+impl Drop for AutoconnectPause<'_> {
+    fn drop(&mut self) {
+        for iface in &self.paused {
+            match set_background_scan(self.client, iface, true) {
+                Ok(()) => tracing::info!("Auto-connect restored"),
+                Err(e) => tracing::warn!("Failed to restore auto-connect: {}", e),
+            }
+        }
+    }
+}
+
+/// Turn the interface's background scanning on or off
+fn set_background_scan(
+    client: &WlanClient,
+    iface: &windows::core::GUID,
+    enabled: bool,
 ) -> anyhow::Result<()> {
+    let value = i32::from(enabled);
+    let status = unsafe {
+        WlanSetInterface(
+            client.handle,
+            iface,
+            wlan_intf_opcode_background_scan_enabled,
+            std::mem::size_of::<i32>() as u32,
+            std::ptr::addr_of!(value).cast(),
+            None,
+        )
+    };
+    if status != 0 {
+        anyhow::bail!(
+            "WlanSetInterface(background_scan_enabled) failed: {}",
+            status
+        );
+    }
+    Ok(())
+}
+
+/// Current connection of the first connected WLAN interface, if any
+pub fn current_connection() -> Option<ConnectedNetwork> {
+    let client = WlanClient::new().ok()?;
+    let ifaces = unsafe { get_wlan_interfaces(client.handle).ok()? };
+    ifaces.iter().find_map(|(iface, _)| {
+        let mut conn = unsafe { get_current_connection(client.handle, iface) }?;
+        conn.ip = ipconfig::for_interface(iface);
+        Some(conn)
+    })
+}
+
+/// First connected WLAN interface with a routable (non-APIPA) IPv4 address, and that address
+pub fn connected_interface_address() -> Option<(windows::core::GUID, std::net::Ipv4Addr)> {
+    let client = WlanClient::new().ok()?;
+    let ifaces = unsafe { get_wlan_interfaces(client.handle).ok()? };
+    ifaces.into_iter().find_map(|(iface, _)| {
+        unsafe { get_current_connection(client.handle, &iface) }?;
+        let addr = ipconfig::for_interface(&iface)?
+            .addresses
+            .iter()
+            .filter_map(|a| a.split('/').next()?.parse::<std::net::Ipv4Addr>().ok())
+            .find(|a| !a.is_link_local())?;
+        Some((iface, addr))
+    })
+}
+
+/// Connection info for an interface that just restored the network, with its IP
+/// configuration read and logged (APIPA, missing gateway or DNS show up right away)
+fn restored_connection(
+    client: &WlanClient,
+    iface: &windows::core::GUID,
+    profile: &str,
+) -> ConnectedNetwork {
+    let mut conn = unsafe { get_current_connection(client.handle, iface) }.unwrap_or_else(|| {
+        ConnectedNetwork {
+            ssid: profile.to_string(),
+            profile: profile.to_string(),
+            bssid: [0; 6],
+            signal_quality: 0,
+            ip: None,
+        }
+    });
+    conn.ip = ipconfig::for_interface(iface);
+    if let Some(ref ip) = conn.ip {
+        ip.log();
+    }
+    conn
+}
+
+/// Poll interval for the first rounds after WlanConnect (association often completes within ~2s)
+const FAST_POLL_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_millis(500);
+
+/// Number of fast polls before falling back to the regular interval
+const FAST_POLL_ROUNDS: u32 = 4;
+
+/// Extra wait granted once when the deadline hits while the interface is still mid-handshake
+const HANDSHAKE_GRACE: tokio::time::Duration = tokio::time::Duration::from_secs(10);
+
+/// States between WlanConnect and connected (discovering, associating, authenticating)
+fn is_handshake_state(state: WLAN_INTERFACE_STATE) -> bool {
+    state == wlan_interface_state_discovering
+        || state == wlan_interface_state_associating
+        || state == wlan_interface_state_authenticating
+}
+
+/// Readable name of a WLAN_INTERFACE_STATE for logs, e.g. "authenticating"
+fn interface_state_name(state: WLAN_INTERFACE_STATE) -> String {
+    let name = match state {
+        s if s == wlan_interface_state_not_ready => "not ready",
+        s if s == wlan_interface_state_connected => "connected",
+        s if s == wlan_interface_state_ad_hoc_network_formed => "ad hoc network formed",
+        s if s == wlan_interface_state_disconnecting => "disconnecting",
+        s if s == wlan_interface_state_disconnected => "disconnected",
+        s if s == wlan_interface_state_associating => "associating",
+        s if s == wlan_interface_state_discovering => "discovering",
+        s if s == wlan_interface_state_authenticating => "authenticating",
+        s => return format!("unknown({})", s.0),
+    };
+    name.to_string()
+}
+
+/// How `poll_wlan_connection_state` ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PollOutcome {
+    Connected,
+    TimedOut,
+    /// Shutdown was requested mid-poll; the caller should stop, not try the next step
+    Cancelled,
+    /// Back to disconnected after a handshake state (`--fail-fast-on-disconnect`): the network
+    /// rejected the connection, e.g. a wrong password
+    Rejected,
+}
+
+/// Consecutive disconnected polls after a handshake state that count as a rejection; one
+/// alone can be the transient disconnect some drivers report mid-authentication
+const REJECTED_POLLS: u32 = 2;
+
+/// Poll WLAN interface connection state until \"connected\" or timeout. Uses connection state, not NCSI.
+/// Polls every 500ms for the first rounds, then every `interval_secs`.
+///
+/// State machine nuances: after WlanConnect an interface normally goes
+/// disconnected -> associating -> authenticating -> connected, but some drivers briefly report
+/// disconnected mid-authentication (common with 802.1X), and some update the current-connection
+/// attributes before the interface state. So a transient disconnected never ends the wait early
+/// (only the deadline does), "connected" is accepted from either source, and a deadline hit
+/// mid-handshake is extended once by `HANDSHAKE_GRACE`. With `opts.accept_associating`, the
+/// handshake states already count as connected (for drivers that linger there).
+/// With `opts.fail_fast_on_disconnect`, `REJECTED_POLLS` disconnected polls in a row after a
+/// handshake state end the wait early as rejected.
+/// Cancelling `opts.shutdown` ends the wait immediately.
+async fn poll_wlan_connection_state(
+    handle: HANDLE,
+    iface: &windows::core::GUID,
+    max_wait_secs: u64,
+    interval_secs: u64,
+    opts: &RecoveryOptions,
+) -> PollOutcome {
+    let start = std::time::Instant::now();
+    let mut max_wait = tokio::time::Duration::from_secs(max_wait_secs);
+    let mut extended = false;
+    let mut last_state = None;
+    let mut handshake_seen = false;
+    let mut disconnected_polls = 0u32;
+    let mut round = 0u32;
+    loop {
+        if start.elapsed() >= max_wait {
+            match last_state {
+                Some(state) if !extended && is_handshake_state(state) => {
+                    extended = true;
+                    max_wait += HANDSHAKE_GRACE;
+                    tracing::info!(
+                        "Still {} at the deadline, waiting up to {}s more",
+                        interface_state_name(state),
+                        HANDSHAKE_GRACE.as_secs()
+                    );
+                }
+                _ => return PollOutcome::TimedOut,
+            }
+        }
+        round += 1;
+        let step = if round <= FAST_POLL_ROUNDS {
+            FAST_POLL_INTERVAL
+        } else {
+            tokio::time::Duration::from_secs(interval_secs)
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(step.min(max_wait.saturating_sub(start.elapsed()))) => {}
+            _ = opts.shutdown.cancelled() => {
+                tracing::info!("Shutdown requested, abandoning WLAN state poll");
+                return PollOutcome::Cancelled;
+            }
+        }
+        let state = unsafe { get_wlan_interface_state(handle, iface) };
+        tracing::info!(
+            "WLAN state poll #{} ({:.1}s/{}s): {}",
+            round,
+            start.elapsed().as_secs_f64(),
+            max_wait.as_secs(),
+            state.map_or("query failed".to_string(), interface_state_name)
+        );
+        if state == Some(wlan_interface_state_connected) {
+            return PollOutcome::Connected;
+        }
+        let attr_state = unsafe { with_connection_attributes(handle, iface, |a| a.isState) };
+        if attr_state == Some(wlan_interface_state_connected) {
+            tracing::info!("Connection attributes report connected");
+            return PollOutcome::Connected;
+        }
+        if let Some(s) = state.filter(|&s| opts.accept_associating && is_handshake_state(s)) {
+            tracing::info!(
+                "Accepting {} as connected (--accept-associating)",
+                interface_state_name(s)
+            );
+            return PollOutcome::Connected;
+        }
+        if state == Some(wlan_interface_state_disconnected) && handshake_seen {
+            disconnected_polls += 1;
+            if opts.fail_fast_on_disconnect && disconnected_polls >= REJECTED_POLLS {
+                tracing::info!("Disconnected again after the handshake: connection rejected");
+                return PollOutcome::Rejected;
+            }
+        } else {
+            disconnected_polls = 0;
+        }
+        if state.is_some_and(is_handshake_state) {
+            handshake_seen = true;
+        }
+        if state.is_some() {
+            last_state = state;
+        }
+    }
+}
+
+/// Whether a profile can be connected as far as 802.1X credentials go
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EnterpriseCheck {
+    /// Not 802.1X, machine authentication, or user credentials applied
+    Ready,
+    /// 802.1X with user authentication and no --eap-user/--eap-pass: the connect would sit in
+    /// authenticating until the 30 s poll times out
+    NeedsCredentials,
+}
+
+/// For an 802.1X profile: apply --eap-user/--eap-pass if given. A user-authentication profile
+/// without them is reported as `NeedsCredentials` so the caller can skip it.
+fn prepare_enterprise_profile(
+    client: &WlanClient,
+    iface: &windows::core::GUID,
+    profile: &str,
+    creds: Option<&EapCredentials>,
+) -> EnterpriseCheck {
+    let xml = match unsafe { get_profile_xml(client.handle, iface, profile, false) } {
+        Ok(x) => x,
+        Err(e) => {
+            tracing::debug!("Read profile XML for \"{}\" failed: {}", profile, e);
+            return EnterpriseCheck::Ready;
+        }
+    };
+    if !eap::is_802_1x(&xml) {
+        return EnterpriseCheck::Ready;
+    }
+    if eap::is_machine_auth(&xml) {
+        tracing::info!("\"{}\" is 802.1X with machine authentication", profile);
+        return EnterpriseCheck::Ready;
+    }
+    match creds {
+        Some(c) if eap::is_peap(&xml) => {
+            tracing::info!(
+                "\"{}\" is 802.1X (PEAP), applying credentials for user \"{}\"",
+                profile,
+                c.user
+            );
+            if let Err(e) =
+                client.set_eap_user_data(iface, profile, &eap::peap_mschapv2_user_data(c))
+            {
+                tracing::warn!("Applying EAP credentials to \"{}\" failed: {}", profile, e);
+            }
+        }
+        Some(_) => {
+            tracing::info!(
+                "\"{}\" is 802.1X but not PEAP; --eap-user/--eap-pass only support PEAP-MSCHAPv2",
+                profile
+            );
+        }
+        None => return EnterpriseCheck::NeedsCredentials,
+    }
+    EnterpriseCheck::Ready
+}
+
+/// When the next WlanConnect may be issued (for --connect-min-gap-ms), across all interfaces
+static NEXT_CONNECT: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Wait until at least `gap` has passed since the previous connect, and reserve the slot so
+/// concurrent interfaces are paced too
+async fn pace_connect(gap: Duration) {
+    if gap.is_zero() {
+        return;
+    }
+    let now = Instant::now();
+    let slot = {
+        let mut next = NEXT_CONNECT.lock().unwrap();
+        let slot = next.map_or(now, |at| at.max(now));
+        *next = Some(slot + gap);
+        slot
+    };
+    if slot > now {
+        tracing::info!(
+            "Pacing connects (--connect-min-gap-ms): waiting {} ms",
+            (slot - now).as_millis()
+        );
+        tokio::time::sleep_until(slot.into()).await;
+    }
+}
+
+/// Issue the connect for one profile attempt, honouring --temporary and --connect-min-gap-ms
+async fn request_connect(
+    client: &WlanClient,
+    iface: &windows::core::GUID,
+    profile: &str,
+    bssids: &[[u8; 6]],
+    opts: &RecoveryOptions,
+) -> anyhow::Result<()> {
+    pace_connect(opts.connect_min_gap).await;
+    let flags = opts.connect_flags.get(profile).copied().unwrap_or(0);
+    if flags != 0 {
+        tracing::debug!("Connecting \"{}\" with dwFlags {:#x}", profile, flags);
+    }
+    if opts.temporary {
+        let xml = unsafe { get_profile_xml(client.handle, iface, profile, true)? };
+        client.connect_temporary(iface, profile, &xml, bssids, flags)
+    } else if bssids.is_empty() {
+        client.connect_profile(iface, profile, flags)
+    } else {
+        client.connect_profile_to_bssids(iface, profile, bssids, flags)
+    }
+}
+
+/// RSSI gain (dBm) another BSS must offer over the current one before --roam-to-best switches
+const ROAM_RSSI_MARGIN_DBM: i32 = 10;
+
+/// After a profile connects: if a noticeably stronger BSS of the same SSID is visible,
+/// reconnect targeting that BSSID (mesh networks often leave the adapter on a far node)
+async fn roam_to_best_bss(
+    client: &WlanClient,
+    iface: &windows::core::GUID,
+    profile: &str,
+    opts: &RecoveryOptions,
+) {
+    let Some(current) = (unsafe { get_current_connection(client.handle, iface) }) else {
+        tracing::debug!("Roam: no current connection info, skip");
+        return;
+    };
+    let bss_list = match unsafe { get_bss_list(client.handle, iface) } {
+        Ok(l) => l,
+        Err(e) => {
+            tracing::info!("Roam: {}, skip", e);
+            return;
+        }
+    };
+    let same_ssid: Vec<_> = bss_list
+        .iter()
+        .filter(|b| b.ssid == current.ssid)
+        .filter(|b| {
+            opts.band_only
+                .is_none_or(|band| band.contains(b.frequency_khz))
+        })
+        .collect();
+    let Some(current_bss) = same_ssid.iter().find(|b| b.bssid == current.bssid) else {
+        tracing::info!(
+            "Roam: current BSS {} not in scan list, skip",
+            format_bssid(&current.bssid)
+        );
+        return;
+    };
+    let Some(best) = same_ssid.iter().max_by_key(|b| b.rssi) else {
+        return;
+    };
+    if best.bssid == current_bss.bssid || best.rssi < current_bss.rssi + ROAM_RSSI_MARGIN_DBM {
+        tracing::info!(
+            "Roam: staying on {} ({} dBm); best of {} BSS(s) for \"{}\" is {} ({} dBm)",
+            format_bssid(&current_bss.bssid),
+            current_bss.rssi,
+            same_ssid.len(),
+            current.ssid,
+            format_bssid(&best.bssid),
+            best.rssi
+        );
+        return;
+    }
+
+    tracing::info!(
+        "Roam: switching \"{}\" from {} ({} dBm) to {} ({} dBm)",
+        current.ssid,
+        format_bssid(&current_bss.bssid),
+        current_bss.rssi,
+        format_bssid(&best.bssid),
+        best.rssi
+    );
+    if let Err(e) = request_connect(client, iface, profile, &[best.bssid], opts).await {
+        tracing::warn!("Roam: reconnect failed: {}", e);
+        return;
+    }
+    match poll_wlan_connection_state(client.handle, iface, 30, 2, opts).await {
+        PollOutcome::Connected => {
+            tracing::info!("Roam: connected to {}", format_bssid(&best.bssid));
+        }
+        PollOutcome::TimedOut => {
+            tracing::warn!("Roam: reconnect to {} timed out", format_bssid(&best.bssid));
+        }
+        PollOutcome::Rejected => {
+            tracing::warn!("Roam: reconnect to {} rejected", format_bssid(&best.bssid));
+        }
+        PollOutcome::Cancelled => {}
+    }
+}
+
+/// `--monitor-only`: scan every interface and log the saved profiles in range with their
+/// signal quality. Read-only: no radio change, no connect.
+pub async fn log_visible_saved_networks(opts: &RecoveryOptions) -> anyhow::Result<()> {
+    let client = WlanClient::new()?;
+    let ifaces = unsafe { get_wlan_interfaces(client.handle)? };
+    if ifaces.is_empty() {
+        tracing::info!("No WLAN interface (radio off or adapter disabled?)");
+        return Ok(());
+    }
+    for (idx, (iface, desc)) in ifaces.iter().enumerate() {
+        scan_and_wait(
+            client.handle,
+            iface,
+            opts.scan_min_interval,
+            opts.scan_passes,
+        )
+        .await;
+        match unsafe { get_visible_saved_networks(client.handle, iface) } {
+            Ok(networks) if networks.is_empty() => {
+                tracing::info!(
+                    "Interface #{} ({}): no saved network in range",
+                    idx + 1,
+                    desc
+                );
+            }
+            Ok(networks) => {
+                let list: Vec<String> = networks
+                    .iter()
+                    .map(|(profile, signal)| format!("\"{}\" {}%", profile, signal))
+                    .collect();
+                tracing::info!(
+                    "Interface #{} ({}): saved networks in range: {}",
+                    idx + 1,
+                    desc,
+                    list.join(", ")
+                );
+            }
+            Err(e) => {
+                tracing::warn!("Interface #{} ({}): {}", idx + 1, desc, e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// One profile tested by `--survey`
+#[derive(Clone, Debug)]
+pub struct SurveyEntry {
+    pub interface: String,
+    pub profile: String,
+    /// Signal quality, 0-100, from the scan
+    pub signal: u32,
+    /// Whether the profile associated within the connect timeout
+    pub connected: bool,
+    /// Whether the probe passed once connected
+    pub probe_ok: bool,
+    /// Probe round-trip, when it passed
+    pub latency: Option<Duration>,
+    /// Addresses assigned while connected
+    pub addresses: Vec<String>,
+}
+
+/// `--survey`: on every interface, connect each saved profile in range in turn, probe it,
+/// record the outcome and disconnect. Unlike recovery it never stops at a success. The
+/// connection each interface had before is restored at the end.
+pub async fn survey(
+    test_network: impl Fn() -> NetworkTestFuture,
+    opts: &RecoveryOptions,
+) -> anyhow::Result<Vec<SurveyEntry>> {
+    let client = WlanClient::new()?;
+    let ifaces = unsafe { get_wlan_interfaces(client.handle)? };
+    if ifaces.is_empty() {
+        anyhow::bail!("No WLAN interface (radio off or adapter disabled?)");
+    }
+    let mut entries = Vec::new();
+    for (iface, desc) in &ifaces {
+        let original = unsafe { get_current_connection(client.handle, iface) }.map(|c| c.profile);
+        scan_and_wait(
+            client.handle,
+            iface,
+            opts.scan_min_interval,
+            opts.scan_passes,
+        )
+        .await;
+        let networks = match unsafe { get_visible_saved_networks(client.handle, iface) } {
+            Ok(networks) => networks,
+            Err(e) => {
+                tracing::warn!("{}: {}, skipped", desc, e);
+                continue;
+            }
+        };
+        tracing::info!(
+            "{}: surveying {} saved network(s) in range",
+            desc,
+            networks.len()
+        );
+        for (profile, signal) in networks {
+            if opts.shutdown.is_cancelled() {
+                break;
+            }
+            tracing::info!("Survey: connecting \"{}\" on {}", profile, desc);
+            let mut entry = SurveyEntry {
+                interface: desc.clone(),
+                profile: profile.clone(),
+                signal,
+                connected: false,
+                probe_ok: false,
+                latency: None,
+                addresses: Vec::new(),
+            };
+            let connected = match request_connect(&client, iface, &profile, &[], opts).await {
+                Ok(()) => {
+                    poll_wlan_connection_state(client.handle, iface, 30, 2, opts).await
+                        == PollOutcome::Connected
+                }
+                Err(e) => {
+                    tracing::info!("Survey: \"{}\": {}", profile, e);
+                    false
+                }
+            };
+            if connected {
+                entry.connected = true;
+                let start = Instant::now();
+                entry.probe_ok = test_network().await;
+                entry.latency = entry.probe_ok.then(|| start.elapsed());
+                entry.addresses = ipconfig::for_interface(iface)
+                    .map(|ip| ip.addresses)
+                    .unwrap_or_default();
+                let _ = client.disconnect(iface);
+            }
+            tracing::info!(
+                "Survey: \"{}\" {}",
+                profile,
+                match (entry.connected, entry.probe_ok) {
+                    (false, _) => "did not connect",
+                    (true, false) => "connected, probe failed",
+                    (true, true) => "connected, probe passed",
+                }
+            );
+            entries.push(entry);
+        }
+        if let Some(ref profile) = original {
+            tracing::info!("Survey done on {}, reconnecting \"{}\"", desc, profile);
+            if let Err(e) = request_connect(&client, iface, profile, &[], opts).await {
+                tracing::warn!("Failed to restore \"{}\": {}", profile, e);
+            } else if poll_wlan_connection_state(client.handle, iface, 30, 2, opts).await
+                != PollOutcome::Connected
+            {
+                tracing::warn!("\"{}\" did not reconnect after the survey", profile);
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Print the `--survey` results, best first: probe passed (fastest first), connected only,
+/// did not connect; stronger signal breaks ties
+pub fn print_survey_report(entries: &[SurveyEntry]) {
+    let mut ranked: Vec<&SurveyEntry> = entries.iter().collect();
+    ranked.sort_by_key(|e| {
+        (
+            std::cmp::Reverse((e.probe_ok, e.connected)),
+            e.latency.unwrap_or(Duration::MAX),
+            std::cmp::Reverse(e.signal),
+        )
+    });
+    let width = ranked
+        .iter()
+        .map(|e| e.profile.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("Profile".len());
+    let iface_width = ranked
+        .iter()
+        .map(|e| e.interface.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("Interface".len());
+    println!(
+        "{:<width$}  {:>6}  {:<10}  {:>7}  {:<iface_width$}  Addresses",
+        "Profile", "Signal", "Result", "Latency", "Interface"
+    );
+    for e in ranked {
+        let result = match (e.connected, e.probe_ok) {
+            (false, _) => "no connect",
+            (true, false) => "no inet",
+            (true, true) => "ok",
+        };
+        let latency = e
+            .latency
+            .map(|l| format!("{} ms", l.as_millis()))
+            .unwrap_or_else(|| "-".to_string());
+        println!(
+            "{:<width$}  {:>5}%  {:<10}  {:>7}  {:<iface_width$}  {}",
+            e.profile,
+            e.signal,
+            result,
+            latency,
+            e.interface,
+            e.addresses.join(", ")
+        );
+    }
+}
+
+/// Signal quality points a saved network must beat the current one by before --roam-floor
+/// switches to it
+const ROAM_FLOOR_MARGIN: u32 = 15;
+
+/// `--roam-floor`: if the current connection is below `floor` (signal quality, 0-100) and a
+/// noticeably stronger saved network is visible, connect to that one instead. Falls back to
+/// the previous profile if the switch does not connect. Returns the new connection, if any.
+pub async fn roam_off_weak_link(
+    floor: u32,
+    opts: &RecoveryOptions,
+) -> anyhow::Result<Option<ConnectedNetwork>> {
+    let client = WlanClient::new()?;
+    let ifaces = unsafe { get_wlan_interfaces(client.handle)? };
+    let Some((iface, desc, current)) = ifaces.iter().find_map(|(iface, desc)| {
+        unsafe { get_current_connection(client.handle, iface) }.map(|c| (iface, desc, c))
+    }) else {
+        return Ok(None);
+    };
+    if current.signal_quality >= floor {
+        return Ok(None);
+    }
+
+    scan_and_wait(
+        client.handle,
+        iface,
+        opts.scan_min_interval,
+        opts.scan_passes,
+    )
+    .await;
+    let visible = unsafe { get_visible_saved_networks(client.handle, iface)? };
+    tracing::debug!("Roam floor: visible saved networks {:?}", visible);
+    let Some((candidate, signal)) = visible
+        .into_iter()
+        .filter(|(p, _)| *p != current.profile)
+        .find(|(_, q)| *q >= current.signal_quality + ROAM_FLOOR_MARGIN)
+    else {
+        tracing::info!(
+            "Roam floor: \"{}\" at {}% on {}, no stronger saved network visible",
+            current.profile,
+            current.signal_quality,
+            desc
+        );
+        return Ok(None);
+    };
+
+    tracing::info!(
+        "Roam floor: \"{}\" at {}% (< {}%), switching to \"{}\" at {}% on {}",
+        current.profile,
+        current.signal_quality,
+        floor,
+        candidate,
+        signal,
+        desc
+    );
+    request_connect(&client, iface, &candidate, &[], opts).await?;
+    match poll_wlan_connection_state(client.handle, iface, 30, 2, opts).await {
+        PollOutcome::Connected => {
+            tracing::info!("Roam floor: connected to \"{}\"", candidate);
+            return Ok(unsafe { get_current_connection(client.handle, iface) });
+        }
+        PollOutcome::Cancelled => return Ok(None),
+        PollOutcome::TimedOut | PollOutcome::Rejected => {}
+    }
+
+    tracing::warn!(
+        "Roam floor: \"{}\" did not connect, returning to \"{}\"",
+        candidate,
+        current.profile
+    );
+    request_connect(&client, iface, &current.profile, &[], opts).await?;
+    poll_wlan_connection_state(client.handle, iface, 30, 2, opts).await;
+    Ok(None)
+}
+
+/// Recovery settings passed from the CLI
+#[derive(Clone, Debug)]
+pub struct RecoveryOptions {
+    /// Which saved profiles to try
+    pub strategy: ConnectStrategy,
+    /// After connecting, switch to a noticeably stronger BSS of the same SSID
+    pub roam_to_best: bool,
+    /// Connect with session-only copies of the saved profiles (preferred-network list untouched)
+    pub temporary: bool,
+    /// Reuse the previous scan results if an interface was scanned more recently than this
+    pub scan_min_interval: Duration,
+    /// WlanScan calls per scan before the available-network list is read
+    pub scan_passes: u32,
+    /// Also count networks seen in scans this recent as visible (zero: current scan only)
+    pub scan_memory: Duration,
+    /// Before anything else, disconnect and reconnect each interface's current profile once
+    pub reconnect_current_first: bool,
+    /// Pause Windows auto-connect (background scanning) while recovery runs
+    pub suspend_autoconnect: bool,
+    /// Connect named profiles, or let Windows auto-connect by its own priority instead
+    pub connect_mode: ConnectMode,
+    /// Run the profile loop on all interfaces at once; the first to restore the network wins
+    pub concurrent_interfaces: bool,
+    /// Keep trying profiles when NCSI passes but the default route is on another adapter
+    pub require_wifi_route: bool,
+    /// Signal order (default) or its reverse
+    pub profile_order: ProfileOrder,
+    /// Profile that restored the network last time; tried first when it is a candidate
+    pub last_good: Option<String>,
+    /// Treat the handshake states as connected when polling after a connect
+    pub accept_associating: bool,
+    /// Give up on a connect that falls back to disconnected after the handshake started
+    pub fail_fast_on_disconnect: bool,
+    /// Only connect to BSSs in this band (`--band-only`)
+    pub band_only: Option<Band>,
+    /// How long to wait for a WLAN interface to appear after enabling the adapter
+    pub adapter_wait: Duration,
+    /// Minimum time between WlanConnect calls (zero: no pacing)
+    pub connect_min_gap: Duration,
+    /// Not elevated (`--best-effort-noadmin`): skip steps that need admin rights
+    pub skip_admin_steps: bool,
+    /// Cancelled on Ctrl+C: polls stop waiting and the round ends without trying more profiles
+    pub shutdown: CancellationToken,
+    /// User credentials applied to 802.1X (PEAP) profiles before connecting
+    pub eap_credentials: Option<EapCredentials>,
+    /// WlanConnect dwFlags per profile name (`--connect-flags`); profiles not listed use 0
+    pub connect_flags: HashMap<String, u32>,
+    /// Remedies tried in order before the regular round (`--escalate`)
+    pub escalate: Vec<crate::escalate::Rung>,
+}
+
+/// One profile connect attempt within a recovery round
+#[derive(Clone, Debug)]
+pub struct ProfileAttempt {
+    pub profile: String,
+    /// Whether this attempt restored the network
+    pub restored: bool,
+}
+
+/// Per-round recovery statistics, filled in by `connect_any_saved_wifi` even when it fails
+#[derive(Clone, Debug, Default)]
+pub struct RecoveryReport {
+    /// WLAN interfaces found (after any adapter enable)
+    pub interfaces: usize,
+    /// Profiles a connect was attempted for, in order
+    pub attempts: Vec<ProfileAttempt>,
+}
+
+/// `--pin-profile`: make sure some interface is connected to `pinned` (matched by profile name
+/// or SSID); otherwise connect it on the first interface that has the profile saved.
+/// Returns true if a reconnect to the pinned profile succeeded, false if it was already connected.
+pub async fn enforce_pinned_profile(pinned: &str, opts: &RecoveryOptions) -> anyhow::Result<bool> {
+    let client = WlanClient::new()?;
+    let ifaces = unsafe { get_wlan_interfaces(client.handle)? };
+    if ifaces.is_empty() {
+        anyhow::bail!("No WLAN interface");
+    }
+
+    let mut target = None;
+    for (iface, desc) in &ifaces {
+        match unsafe { get_current_connection(client.handle, iface) } {
+            Some(conn) if conn.profile == pinned || conn.ssid == pinned => {
+                tracing::debug!("Pinned \"{}\" is connected", pinned);
+                return Ok(false);
+            }
+            Some(conn) => {
+                tracing::info!(
+                    "Connected to \"{}\", not pinned \"{}\"",
+                    conn.profile,
+                    pinned
+                );
+            }
+            None => {}
+        }
+        if target.is_none() {
+            let saved = unsafe { get_saved_profiles(client.handle, iface) }.unwrap_or_default();
+            if saved.iter().any(|p| p == pinned) {
+                target = Some((*iface, desc.as_str()));
+            }
+        }
+    }
+
+    let Some((iface, desc)) = target else {
+        anyhow::bail!(
+            "Pinned profile \"{}\" is not saved on any interface",
+            pinned
+        );
+    };
+    tracing::info!("Connecting pinned profile \"{}\" on {}", pinned, desc);
+    request_connect(&client, &iface, pinned, &[], opts).await?;
+    if poll_wlan_connection_state(client.handle, &iface, 30, 2, opts).await
+        != PollOutcome::Connected
+    {
+        anyhow::bail!("Pinned profile \"{}\" did not reach connected", pinned);
+    }
+    tracing::info!("Pinned profile \"{}\" connected", pinned);
+    Ok(true)
+}
+
+/// Association recorded before a recovery round (`--restore-on-fail`)
+#[derive(Clone, Debug)]
+pub struct AssociationSnapshot {
+    iface: windows::core::GUID,
+    desc: String,
+    pub profile: String,
+    pub ssid: String,
+}
+
+/// The first interface's current association, if any interface is connected
+pub fn association_snapshot() -> Option<AssociationSnapshot> {
+    let client = WlanClient::new().ok()?;
+    let ifaces = unsafe { get_wlan_interfaces(client.handle).ok()? };
+    ifaces.into_iter().find_map(|(iface, desc)| {
+        let conn = unsafe { get_current_connection(client.handle, &iface) }?;
+        Some(AssociationSnapshot {
+            iface,
+            desc,
+            profile: conn.profile,
+            ssid: conn.ssid,
+        })
+    })
+}
+
+/// `--restore-on-fail`: reconnect the interface to the profile it was on before the round, so
+/// a failed recovery does not leave it disconnected. Nothing to do if it is already back on it.
+pub async fn restore_association(
+    snapshot: &AssociationSnapshot,
+    opts: &RecoveryOptions,
+) -> anyhow::Result<()> {
+    let client = WlanClient::new()?;
+    if let Some(conn) = unsafe { get_current_connection(client.handle, &snapshot.iface) } {
+        if conn.profile == snapshot.profile {
+            tracing::info!(
+                "{} is still on \"{}\", nothing to restore",
+                snapshot.desc,
+                snapshot.profile
+            );
+            return Ok(());
+        }
+    }
+    tracing::info!(
+        "Restoring pre-recovery association: \"{}\" on {}",
+        snapshot.profile,
+        snapshot.desc
+    );
+    request_connect(&client, &snapshot.iface, &snapshot.profile, &[], opts).await?;
+    if poll_wlan_connection_state(client.handle, &snapshot.iface, 30, 2, opts).await
+        != PollOutcome::Connected
+    {
+        anyhow::bail!(
+            "\"{}\" did not reach connected on {}",
+            snapshot.profile,
+            snapshot.desc
+        );
+    }
+    tracing::info!("Pre-recovery association \"{}\" restored", snapshot.profile);
+    Ok(())
+}
+
+/// Filter profiles by strategy: only those we should try. `Explicit` returns the saved
+/// profiles in the order the names were given (duplicates dropped).
+fn filter_profiles_by_strategy(
+    saved: &[String],
+    strategy: &ConnectStrategy,
+    available_names: Option<&HashSet<String>>,
+) -> Vec<String> {
+    match strategy {
+        ConnectStrategy::ScanOnly => {
+            let avail = match available_names {
+                Some(s) => s,
+                None => return Vec::new(),
+            };
+            saved
+                .iter()
+                .filter(|p| avail.contains(*p))
+                .cloned()
+                .collect()
+        }
+        ConnectStrategy::All => saved.to_vec(),
+        ConnectStrategy::Explicit(names) => {
+            let mut listed: Vec<String> = Vec::new();
+            for name in names {
+                if let Some(p) = saved.iter().find(|p| profile_name_matches(p, name)) {
+                    if !listed.contains(p) {
+                        listed.push(p.clone());
+                    }
+                }
+            }
+            listed
+        }
+    }
+}
+
+/// Whether a saved profile name matches a `--profiles` entry: Windows treats profile names
+/// case-insensitively, and stray whitespace in CLI input should not exclude a profile
+fn profile_name_matches(saved: &str, wanted: &str) -> bool {
+    saved.trim().to_lowercase() == wanted.trim().to_lowercase()
+}
+
+/// Boxed future returned by the post-connect network test
+pub type NetworkTestFuture = std::pin::Pin<Box<dyn std::future::Future<Output = bool> + Send>>;
+
+/// After a connect reached connected: whether the interface is associated with the SSID the
+/// profile names. A forced connect can end up on another network Windows auto-connected to.
+/// True when either side is unknown (no SSID in the profile XML, or no connection attributes).
+fn connected_ssid_matches(client: &WlanClient, iface: &windows::core::GUID, profile: &str) -> bool {
+    let expected = unsafe { get_profile_xml(client.handle, iface, profile, false) }
+        .ok()
+        .as_deref()
+        .and_then(profile_ssid);
+    let Some(expected) = expected else {
+        return true;
+    };
+    let Some(current) = (unsafe { get_current_connection(client.handle, iface) }) else {
+        return true;
+    };
+    let expected = String::from_utf8_lossy(&expected);
+    if current.ssid == expected {
+        return true;
+    }
+    tracing::warn!(
+        "Connected to \"{}\" (profile \"{}\") instead of \"{}\" from profile \"{}\", try next",
+        current.ssid,
+        current.profile,
+        expected,
+        profile
+    );
+    false
+}
+
+/// After NCSI passed: warn when the default route egresses through another adapter (e.g.
+/// Ethernet), i.e. this Wi-Fi connection is not what carries traffic. Returns false only when
+/// --require-wifi-route asks to keep trying profiles in that case.
+fn wifi_route_ok(iface: &windows::core::GUID, profile: &str, opts: &RecoveryOptions) -> bool {
+    if route::default_route_via(iface) != Some(false) {
+        return true;
+    }
+    tracing::warn!(
+        "NCSI passed, but the default route does not go through Wi-Fi (\"{}\"); \
+         another adapter is providing connectivity",
+        profile
+    );
+    if opts.require_wifi_route {
+        tracing::info!("\"{}\" does not carry the default route, try next", profile);
+        return false;
+    }
+    true
+}
+
+/// `--reconnect-current-first`: disconnect and reconnect the profile each connected interface
+/// is on, once, and re-probe ("connected but no internet" is often fixed by a fresh association)
+async fn reconnect_current(
+    client: &WlanClient,
+    ifaces: &[(windows::core::GUID, String)],
+    test_network: &impl Fn() -> NetworkTestFuture,
+    opts: &RecoveryOptions,
+    report: &mut RecoveryReport,
+) -> Option<ConnectedNetwork> {
+    for (iface, desc) in ifaces {
+        let Some(current) = (unsafe { get_current_connection(client.handle, iface) }) else {
+            continue;
+        };
+        tracing::info!(
+            "Reconnecting current profile \"{}\" on {} first",
+            current.profile,
+            desc
+        );
+        report.attempts.push(ProfileAttempt {
+            profile: current.profile.clone(),
+            restored: false,
+        });
+        if let Err(e) = client.disconnect(iface) {
+            tracing::info!("{}, skip reconnect", e);
+            continue;
+        }
+        if let Err(e) = request_connect(client, iface, &current.profile, &[], opts).await {
+            tracing::info!("Reconnect \"{}\" failed: {}", current.profile, e);
+            continue;
+        }
+        match poll_wlan_connection_state(client.handle, iface, 30, 2, opts).await {
+            PollOutcome::Connected => {}
+            PollOutcome::TimedOut => {
+                tracing::info!("Reconnect \"{}\" timed out", current.profile);
+                continue;
+            }
+            PollOutcome::Rejected => {
+                tracing::info!("Reconnect \"{}\" rejected", current.profile);
+                continue;
+            }
+            PollOutcome::Cancelled => return None,
+        }
+        if test_network().await && wifi_route_ok(iface, &current.profile, opts) {
+            tracing::info!("Network restored by reconnecting \"{}\"", current.profile);
+            if let Some(attempt) = report.attempts.last_mut() {
+                attempt.restored = true;
+            }
+            return Some(restored_connection(client, iface, &current.profile));
+        }
+        tracing::info!(
+            "Reconnected \"{}\" but network still down, trying other profiles",
+            current.profile
+        );
+    }
+    None
+}
+
+/// `--escalate reconnect`: `reconnect_current` on a fresh client over all interfaces
+pub async fn reconnect_connected(
+    test_network: impl Fn() -> NetworkTestFuture,
+    opts: &RecoveryOptions,
+    report: &mut RecoveryReport,
+) -> anyhow::Result<Option<ConnectedNetwork>> {
+    let client = WlanClient::new()?;
+    let ifaces = unsafe { get_wlan_interfaces(client.handle)? };
+    Ok(reconnect_current(&client, &ifaces, &test_network, opts, report).await)
+}
+
+/// `--strict-autoconnect`: per interface, let Windows connect by its own priority and wait.
+/// When WlanConnect rejects auto mode, a scan still nudges AutoConfig into reconnecting.
+async fn autoconnect_any(
+    client: &WlanClient,
+    ifaces: &[(windows::core::GUID, String)],
+    test_network: impl Fn() -> NetworkTestFuture,
+    opts: &RecoveryOptions,
+    report: &mut RecoveryReport,
+) -> anyhow::Result<ConnectedNetwork> {
+    for (idx, (iface, desc)) in ifaces.iter().enumerate() {
+        tracing::info!(
+            "Interface #{} ({}): requesting Windows auto-connect",
+            idx + 1,
+            desc
+        );
+        if let Err(e) = client.connect_auto(iface) {
+            tracing::info!("{}, scanning to trigger AutoConfig instead", e);
+            unsafe {
+                let _ = WlanScan(client.handle, iface, None, None, None);
+            }
+        }
+        match poll_wlan_connection_state(client.handle, iface, 30, 2, opts).await {
+            PollOutcome::Connected => {}
+            PollOutcome::TimedOut | PollOutcome::Rejected => {
+                tracing::info!(
+                    "Interface #{} ({}): Windows did not auto-connect",
+                    idx + 1,
+                    desc
+                );
+                continue;
+            }
+            PollOutcome::Cancelled => break,
+        }
+        let Some(connected) = (unsafe { get_current_connection(client.handle, iface) }) else {
+            continue;
+        };
+        tracing::info!(
+            "Windows auto-connected \"{}\", checking network...",
+            connected.profile
+        );
+        let restored = test_network().await && wifi_route_ok(iface, &connected.profile, opts);
+        report.attempts.push(ProfileAttempt {
+            profile: connected.profile.clone(),
+            restored,
+        });
+        if restored {
+            tracing::info!("Network restored via \"{}\"", connected.profile);
+            return Ok(restored_connection(client, iface, &connected.profile));
+        }
+        tracing::info!(
+            "\"{}\" connected but did not restore network",
+            connected.profile
+        );
+    }
+    anyhow::bail!("Windows auto-connect did not restore network");
+}
+
+/// Candidate order after the strategy filter: strongest visible signal first, profiles missing
+/// from the scan results after all visible ones. Ties keep the saved-profile list order (the
+/// Windows auto-connect priority), which is unique, so the order is deterministic for a given
+/// scan. `ProfileOrder::Reverse` returns exactly the opposite. The last-good profile is then
+/// moved to the front.
+fn order_profiles(
+    profiles: Vec<String>,
+    signals: &HashMap<String, u32>,
+    order: ProfileOrder,
+) -> Vec<String> {
+    let mut indexed: Vec<(usize, String)> = profiles.into_iter().enumerate().collect();
+    indexed.sort_by_key(|(idx, profile)| (std::cmp::Reverse(signals.get(profile).copied()), *idx));
+    if order == ProfileOrder::Reverse {
+        indexed.reverse();
+    }
+    indexed.into_iter().map(|(_, profile)| profile).collect()
+}
+
+/// After enabling an adapter: re-enumerate WLAN interfaces every second until one shows up
+/// or `wait` runs out (slow USB dongles can take well over a few seconds to initialize)
+async fn wait_for_interfaces(
+    client: &WlanClient,
+    wait: Duration,
+) -> anyhow::Result<Vec<(windows::core::GUID, String)>> {
+    let start = Instant::now();
+    let mut attempt = 0u32;
+    loop {
+        tokio::time::sleep(Duration::from_secs(1).min(wait.saturating_sub(start.elapsed()))).await;
+        attempt += 1;
+        let ifaces = unsafe { get_wlan_interfaces(client.handle)? };
+        tracing::info!(
+            "Re-enum #{} ({:.0}s/{}s): {} WLAN interface(s)",
+            attempt,
+            start.elapsed().as_secs_f64(),
+            wait.as_secs(),
+            ifaces.len()
+        );
+        if !ifaces.is_empty() || start.elapsed() >= wait {
+            return Ok(ifaces);
+        }
+    }
+}
+
+/// Enumerate saved profiles, filter by strategy, try connecting until NCSI passes.
+/// Returns the network that restored connectivity; `report` records what was attempted.
+pub async fn connect_any_saved_wifi(
+    test_network: impl Fn() -> NetworkTestFuture + Send + Sync + 'static,
+    opts: &RecoveryOptions,
+    report: &mut RecoveryReport,
+) -> anyhow::Result<ConnectedNetwork> {
     tracing::info!("Initializing WLAN client...");
     let client = WlanClient::new()?;
     tracing::info!("WLAN client ready");
@@ -272,94 +2188,467 @@ pub async fn connect_any_saved_wifi(
     let mut ifaces = unsafe { get_wlan_interfaces(client.handle)? };
     tracing::info!("Found {} WLAN interface(s)", ifaces.len());
 
-    if ifaces.is_empty() {
+    if ifaces.is_empty() && adapter::wifi_hardware_present() == Some(false) {
+        tracing::error!("No Wi-Fi hardware detected — recovery impossible");
+        return Err(NoWifiHardware.into());
+    }
+
+    if ifaces.is_empty() && opts.skip_admin_steps {
+        tracing::warn!(
+            "No WLAN interface; adapter may be disabled, but enabling it needs administrator \
+             rights (--best-effort-noadmin), skipped"
+        );
+    } else if ifaces.is_empty() {
         tracing::warn!("No WLAN interface; adapter may be disabled, trying to enable...");
         if adapter::try_enable_wlan_adapter() {
-            tracing::info!("Waiting 3s then re-enumerating WLAN interfaces...");
-            tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
-            ifaces = unsafe { get_wlan_interfaces(client.handle)? };
-            tracing::info!("Re-enum: {} WLAN interface(s)", ifaces.len());
+            ifaces = wait_for_interfaces(&client, opts.adapter_wait).await?;
         }
     }
 
+    report.interfaces = ifaces.len();
     if ifaces.is_empty() {
         anyhow::bail!("No WLAN interface (tried enabling common adapters)");
     }
 
-    let mut tried = 0u32;
-    for (idx, iface) in ifaces.iter().enumerate() {
-        let saved = match unsafe { get_saved_profiles(client.handle, iface) } {
-            Ok(p) => p,
+    let _pause = opts
+        .suspend_autoconnect
+        .then(|| AutoconnectPause::new(&client, &ifaces));
+
+    if opts.reconnect_current_first {
+        if let Some(connected) =
+            reconnect_current(&client, &ifaces, &test_network, opts, report).await
+        {
+            return Ok(connected);
+        }
+    }
+
+    if opts.connect_mode == ConnectMode::Auto {
+        return autoconnect_any(&client, &ifaces, test_network, opts, report).await;
+    }
+
+    if opts.concurrent_interfaces && ifaces.len() > 1 {
+        return recover_concurrently(ifaces, Arc::new(test_network), opts, report).await;
+    }
+
+    // Grows when an adapter disappears mid-round: re-enumerating picks up a replugged one
+    // (it comes back under a new GUID)
+    let mut idx = 0;
+    while idx < ifaces.len() {
+        if opts.shutdown.is_cancelled() {
+            anyhow::bail!("Recovery cancelled by shutdown");
+        }
+        let (iface, desc) = ifaces[idx].clone();
+        let restored = recover_on_interface(
+            &client,
+            idx,
+            &iface,
+            &desc,
+            &test_network,
+            opts,
+            &mut report.attempts,
+        )
+        .await;
+        if let Some(connected) = restored {
+            return Ok(connected);
+        }
+        if !interface_present(&client, &iface) {
+            let fresh = unsafe { get_wlan_interfaces(client.handle) }.unwrap_or_default();
+            for (guid, name) in fresh {
+                if !ifaces.iter().any(|(g, _)| *g == guid) {
+                    tracing::info!("Re-enumerated: new WLAN interface {}", name);
+                    ifaces.push((guid, name));
+                }
+            }
+            report.interfaces = report.interfaces.max(ifaces.len());
+        }
+        idx += 1;
+    }
+
+    tracing::warn!(
+        "Tried {} profile(s), none restored network",
+        report.attempts.len()
+    );
+    anyhow::bail!("No saved Wi-Fi profile could establish network");
+}
+
+/// `--concurrent-interfaces`: run the profile loop on every interface at once (each with its
+/// own WLAN handle) and return as soon as one restores the network; the others are aborted.
+/// Attempts still in flight on aborted interfaces are not recorded in `report`.
+async fn recover_concurrently<F>(
+    ifaces: Vec<(windows::core::GUID, String)>,
+    test_network: Arc<F>,
+    opts: &RecoveryOptions,
+    report: &mut RecoveryReport,
+) -> anyhow::Result<ConnectedNetwork>
+where
+    F: Fn() -> NetworkTestFuture + Send + Sync + 'static,
+{
+    tracing::info!("Recovering on {} interfaces concurrently", ifaces.len());
+    let mut tasks = tokio::task::JoinSet::new();
+    for (idx, (iface, desc)) in ifaces.into_iter().enumerate() {
+        let test_network = Arc::clone(&test_network);
+        let opts = opts.clone();
+        tasks.spawn(async move {
+            let mut attempts = Vec::new();
+            let restored = match WlanClient::new() {
+                Ok(client) => {
+                    recover_on_interface(
+                        &client,
+                        idx,
+                        &iface,
+                        &desc,
+                        &*test_network,
+                        &opts,
+                        &mut attempts,
+                    )
+                    .await
+                }
+                Err(e) => {
+                    tracing::warn!("Interface #{} ({}): {}", idx + 1, desc, e);
+                    None
+                }
+            };
+            (restored, attempts)
+        });
+    }
+
+    while let Some(joined) = tasks.join_next().await {
+        let (restored, attempts) = match joined {
+            Ok(done) => done,
             Err(e) => {
-                tracing::warn!(
-                    "Interface #{}: get profile list failed: {}, skip",
-                    idx + 1,
-                    e
-                );
+                tracing::warn!("Interface recovery task failed: {}", e);
                 continue;
             }
         };
-        tracing::info!("Interface #{}: {} saved profile(s)", idx + 1, saved.len());
+        report.attempts.extend(attempts);
+        if let Some(connected) = restored {
+            tasks.abort_all();
+            return Ok(connected);
+        }
+    }
 
-        let available_names = match &strategy {
-            ConnectStrategy::ScanOnly => {
-                tracing::info!("Scanning visible networks (connect only in-range)...");
-                unsafe {
-                    let _ = WlanScan(client.handle, iface, None, None, None);
-                }
-                tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
-                match unsafe { get_available_network_names(client.handle, iface, false) } {
-                    Ok(n) => {
-                        tracing::debug!("{} visible network(s): {:?}", n.len(), n);
-                        Some(n)
-                    }
-                    Err(e) => {
-                        tracing::warn!("Get visible list failed: {}, skip interface", e);
-                        continue;
+    tracing::warn!(
+        "Tried {} profile(s) across interfaces, none restored network",
+        report.attempts.len()
+    );
+    anyhow::bail!("No saved Wi-Fi profile could establish network");
+}
+
+/// Scan (per strategy), filter and try profiles on one interface until NCSI passes.
+/// Each try is appended to `attempts`; returns the network that restored connectivity.
+async fn recover_on_interface(
+    client: &WlanClient,
+    idx: usize,
+    iface: &windows::core::GUID,
+    desc: &str,
+    test_network: &impl Fn() -> NetworkTestFuture,
+    opts: &RecoveryOptions,
+    attempts: &mut Vec<ProfileAttempt>,
+) -> Option<ConnectedNetwork> {
+    let strategy = &opts.strategy;
+    let saved = match unsafe { get_saved_profiles(client.handle, iface) } {
+        Ok(p) => p,
+        Err(e) => {
+            tracing::warn!(
+                "Interface #{} ({}): get profile list failed: {}, skip",
+                idx + 1,
+                desc,
+                e
+            );
+            return None;
+        }
+    };
+    tracing::info!(
+        "Interface #{} ({}): {} saved profile(s)",
+        idx + 1,
+        desc,
+        saved.len()
+    );
+
+    let mut available_names = match strategy {
+        ConnectStrategy::ScanOnly => {
+            tracing::info!("Scanning visible networks (connect only in-range)...");
+            scan_and_wait(
+                client.handle,
+                iface,
+                opts.scan_min_interval,
+                opts.scan_passes,
+            )
+            .await;
+            match unsafe { get_available_network_names(client.handle, iface) } {
+                Ok(n) => {
+                    if opts.scan_passes > 1 {
+                        tracing::info!(
+                            "{} unique network(s) after {} scan passes",
+                            n.len(),
+                            opts.scan_passes
+                        );
                     }
+                    tracing::debug!("{} visible network(s): {:?}", n.len(), n);
+                    Some(n)
+                }
+                Err(e) => {
+                    tracing::warn!("Get visible list failed: {}, skip {}", e, desc);
+                    return None;
                 }
             }
-            _ => None,
-        };
+        }
+        _ => None,
+    };
 
-        let profiles = filter_profiles_by_strategy(&saved, &strategy, available_names.as_ref());
-        if profiles.is_empty() {
-            tracing::info!("No profiles to try after filter (strategy: {:?})", strategy);
+    let mut signals: HashMap<String, u32> =
+        unsafe { get_visible_saved_networks(client.handle, iface) }
+            .map(|v| v.into_iter().collect())
+            .unwrap_or_default();
+    if let Some(ref mut names) = available_names {
+        if !opts.scan_memory.is_zero() {
+            merge_scan_memory(iface, names, &mut signals, opts.scan_memory);
+        }
+    }
+    let filtered = filter_profiles_by_strategy(&saved, strategy, available_names.as_ref());
+    let explicit = matches!(strategy, ConnectStrategy::Explicit(_));
+    let mut profiles = if explicit {
+        // --profiles: strictly the order given on the command line
+        let mut listed = filtered;
+        if opts.profile_order == ProfileOrder::Reverse {
+            listed.reverse();
+        }
+        listed
+    } else {
+        order_profiles(filtered, &signals, opts.profile_order)
+    };
+    // The last-good profile goes first, except over an explicit --profiles order
+    if let Some(last_good) = opts.last_good.as_ref().filter(|_| !explicit) {
+        if let Some(pos) = profiles.iter().position(|p| p == last_good) {
+            tracing::info!("Trying last-good profile \"{}\" first", last_good);
+            profiles[..=pos].rotate_right(1);
+        }
+    }
+    if profiles.is_empty() {
+        tracing::info!(
+            "{}: no profiles to try after filter (strategy: {:?})",
+            desc,
+            strategy
+        );
+        return None;
+    }
+    let profiles_count = profiles.len();
+    tracing::debug!(
+        "{} profile(s) to try on this interface: {:?}",
+        profiles_count,
+        profiles
+    );
+
+    for (tried, profile) in profiles.into_iter().enumerate() {
+        if opts.shutdown.is_cancelled() {
+            return None;
+        }
+        if prepare_enterprise_profile(client, iface, &profile, opts.eap_credentials.as_ref())
+            == EnterpriseCheck::NeedsCredentials
+        {
+            tracing::info!(
+                "[{}/{}] \"{}\" skipped: needs credentials (802.1X user authentication; pass \
+                 --eap-user/--eap-pass)",
+                tried + 1,
+                profiles_count,
+                profile
+            );
             continue;
         }
-        let profiles_count = profiles.len();
-        tracing::debug!(
-            "{} profile(s) to try on this interface: {:?}",
+        attempts.push(ProfileAttempt {
+            profile: profile.clone(),
+            restored: false,
+        });
+        tracing::info!(
+            "[{}/{}] Connecting: \"{}\"",
+            tried + 1,
             profiles_count,
-            profiles
+            profile
         );
 
-        for profile in profiles {
-            tried += 1;
-            tracing::info!("[{}/{}] Connecting: \"{}\"", tried, profiles_count, profile);
-
-            if let Err(e) = client.connect_profile(iface, &profile) {
-                tracing::info!("Connect \"{}\" failed: {}", profile, e);
-                continue;
+        let span = tracing::info_span!(
+            "connect",
+            profile = %profile,
+            connect_ms = tracing::field::Empty,
+            poll_ms = tracing::field::Empty,
+            ncsi_ms = tracing::field::Empty,
+        );
+        let restored = try_profile(client, iface, &profile, test_network, opts)
+            .instrument(span)
+            .await;
+        if let Some(connected) = restored {
+            if let Some(attempt) = attempts.last_mut() {
+                attempt.restored = true;
             }
+            return Some(connected);
+        }
+        if !interface_present(client, iface) {
+            tracing::warn!(
+                "Interface #{} ({}): adapter removed, skipping its remaining profiles",
+                idx + 1,
+                desc
+            );
+            return None;
+        }
+    }
+    None
+}
 
-            tracing::info!("Connect requested, polling WLAN state (every 2s, up to 30s)...");
-            if !poll_wlan_connection_state(client.handle, iface, 30, 2).await {
+/// One profile attempt: connect, poll until associated, then check the network. Runs inside
+/// the "connect" span and records the time spent in each phase as span fields.
+async fn try_profile(
+    client: &WlanClient,
+    iface: &windows::core::GUID,
+    profile: &str,
+    test_network: &impl Fn() -> NetworkTestFuture,
+    opts: &RecoveryOptions,
+) -> Option<ConnectedNetwork> {
+    let span = tracing::Span::current();
+    let bssids = match opts.band_only {
+        Some(band) => match band_bssids(client, iface, profile, band) {
+            Ok(bssids) => {
                 tracing::info!(
-                    "\"{}\" timed out (never reached connected), try next",
-                    profile
+                    "\"{}\": restricting the connect to {} {} BSS(s) (--band-only)",
+                    profile,
+                    bssids.len(),
+                    band
                 );
-                continue;
+                bssids
             }
-            tracing::info!("WLAN connected, checking network...");
-            if test_network().await {
-                tracing::info!("Network restored via \"{}\"", profile);
-                return Ok(());
+            Err(reason) => {
+                tracing::info!("Skipping \"{}\": {} (--band-only)", profile, reason);
+                return None;
             }
-            tracing::info!("\"{}\" connected but NCSI failed, try next", profile);
+        },
+        None => Vec::new(),
+    };
+    // Includes any --connect-min-gap-ms pacing
+    let phase = Instant::now();
+    let connect = request_connect(client, iface, profile, &bssids, opts).await;
+    span.record("connect_ms", phase.elapsed().as_millis() as u64);
+    if let Err(e) = connect {
+        if e.downcast_ref::<InterfaceRemoved>().is_some() {
+            tracing::warn!("Connect \"{}\" failed: adapter removed ({})", profile, e);
+        } else {
+            tracing::info!("Connect \"{}\" failed: {}", profile, e);
         }
+        return None;
     }
 
-    tracing::warn!("Tried {} profile(s), none restored network", tried);
-    anyhow::bail!("No saved Wi-Fi profile could establish network");
+    tracing::info!("Connect requested, polling WLAN state (every 0.5s, then 2s, up to 30s)...");
+    let phase = Instant::now();
+    let outcome = poll_wlan_connection_state(client.handle, iface, 30, 2, opts).await;
+    span.record("poll_ms", phase.elapsed().as_millis() as u64);
+    if outcome == PollOutcome::Cancelled {
+        return None;
+    }
+    if outcome == PollOutcome::Rejected {
+        tracing::info!(
+            "\"{}\" rejected the connection (wrong password or credentials?), try next",
+            profile
+        );
+        return None;
+    }
+    if outcome == PollOutcome::TimedOut {
+        tracing::info!(
+            "\"{}\" timed out (never reached connected), try next",
+            profile
+        );
+        return None;
+    }
+    if !connected_ssid_matches(client, iface, profile) {
+        return None;
+    }
+    if opts.roam_to_best {
+        roam_to_best_bss(client, iface, profile, opts).await;
+    }
+    tracing::info!("WLAN connected, checking network...");
+    let phase = Instant::now();
+    let ok = test_network().await;
+    span.record("ncsi_ms", phase.elapsed().as_millis() as u64);
+    if !ok {
+        tracing::info!("\"{}\" connected but NCSI failed, try next", profile);
+        return None;
+    }
+    if !wifi_route_ok(iface, profile, opts) {
+        return None;
+    }
+    tracing::info!("Network restored via \"{}\"", profile);
+    Some(restored_connection(client, iface, profile))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn signals(list: &[(&str, u32)]) -> HashMap<String, u32> {
+        list.iter().map(|(p, s)| (p.to_string(), *s)).collect()
+    }
+
+    #[test]
+    fn order_is_deterministic_for_a_fixed_input() {
+        // Cafe and Home tie at 70: saved-list order decides; Hotel was not seen in the scan
+        let saved = names(&["Hotel", "Cafe", "Office", "Home"]);
+        let seen = signals(&[("Cafe", 70), ("Office", 90), ("Home", 70)]);
+        let first = order_profiles(saved.clone(), &seen, ProfileOrder::Signal);
+        assert_eq!(first, names(&["Office", "Cafe", "Home", "Hotel"]));
+        for _ in 0..10 {
+            assert_eq!(
+                order_profiles(saved.clone(), &seen, ProfileOrder::Signal),
+                first
+            );
+        }
+    }
+
+    #[test]
+    fn reverse_order_is_exact_reverse_of_signal_order() {
+        let saved = names(&["Hotel", "Cafe", "Office", "Airport", "Home"]);
+        let seen = signals(&[("Cafe", 70), ("Office", 90), ("Home", 70)]);
+        let mut forward = order_profiles(saved.clone(), &seen, ProfileOrder::Signal);
+        assert_eq!(
+            forward,
+            names(&["Office", "Cafe", "Home", "Hotel", "Airport"])
+        );
+        forward.reverse();
+        assert_eq!(order_profiles(saved, &seen, ProfileOrder::Reverse), forward);
+    }
+
+    #[test]
+    fn profile_name_match_ignores_case() {
+        assert!(profile_name_matches("Home", "home"));
+    }
+
+    #[test]
+    fn profile_name_match_ignores_surrounding_whitespace() {
+        assert!(profile_name_matches("Home", " Home "));
+    }
+
+    #[test]
+    fn profile_name_match_is_not_a_prefix_match() {
+        assert!(!profile_name_matches("Home", "Home2"));
+    }
+
+    #[test]
+    fn explicit_strategy_keeps_cli_order() {
+        let saved = names(&["Home", "Office", "Cafe"]);
+        let strategy = ConnectStrategy::Explicit(names(&["Office", "Home"]));
+        assert_eq!(
+            filter_profiles_by_strategy(&saved, &strategy, None),
+            names(&["Office", "Home"])
+        );
+    }
+
+    #[test]
+    fn explicit_strategy_drops_duplicates() {
+        let saved = names(&["Home", "Office", "Cafe"]);
+        let strategy = ConnectStrategy::Explicit(names(&["Office", "Home", "office", "Office"]));
+        assert_eq!(
+            filter_profiles_by_strategy(&saved, &strategy, None),
+            names(&["Office", "Home"])
+        );
+    }
 }